@@ -0,0 +1,44 @@
+// compares the node counts of the search with each pruning technique turned off
+// usage: cargo run --release --example bench [depth]
+use chess::Color;
+use chess_ai::bench::run_bench;
+use chess_ai::Bot;
+use std::env;
+
+// turns off one part of the search
+type Configure = fn(&mut Bot);
+
+fn main() {
+    let depth = env::args().nth(1).and_then(|d| d.parse().ok()).unwrap_or(7);
+
+    let configurations: [(&str, Configure); 5] = [
+        ("all enabled", |_| {}),
+        ("no null move pruning", |bot| {
+            bot.set_null_move_pruning(false)
+        }),
+        ("no late move reductions", |bot| {
+            bot.set_late_move_reductions(false)
+        }),
+        ("no principal variation search", |bot| {
+            bot.set_principal_variation_search(false)
+        }),
+        ("no aspiration windows", |bot| {
+            bot.set_aspiration_windows(false)
+        }),
+    ];
+
+    println!("depth {}", depth);
+    for (name, configure) in configurations.iter() {
+        let mut bot = Bot::new(Color::White, depth);
+        configure(&mut bot);
+        let result = run_bench(&mut bot);
+        println!(
+            "{:<32} nodes {:>10} qnodes {:>10} total {:>10} time {:.2?}",
+            name,
+            result.nodes,
+            result.qnodes,
+            result.nodes + result.qnodes,
+            result.time
+        );
+    }
+}
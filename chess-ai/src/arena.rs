@@ -0,0 +1,343 @@
+// headless matches between two players, to compare bot configurations
+use chess::{Board, Color, Game};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::clock::{Clock, ClockSettings};
+use crate::history::fen_halfmove_clock;
+use crate::outcome::Outcome;
+use crate::player::{wait_for_action, Player, PlayerAction};
+use crate::record::GameRecord;
+use crate::stats::MatchScore;
+
+pub struct MatchSettings {
+    pub games: usize,
+    // starting positions with their halfmove clocks, each is played twice with the colors swapped;
+    // the standard position if empty
+    pub openings: Vec<(Board, u32)>,
+    // None to play with the players' own limits per move
+    pub clock: Option<ClockSettings>,
+    // number of games played at the same time
+    pub concurrency: usize,
+}
+
+// a finished game of a match
+pub struct MatchGame {
+    // starting at 1
+    pub number: usize,
+    // the color of the first player
+    pub first: Color,
+    pub record: GameRecord,
+    pub outcome: Outcome,
+    // why a player resigned, if it failed
+    pub error: Option<String>,
+}
+
+impl MatchGame {
+    // the two games of a pair start from the same opening, with the colors swapped
+    pub fn pair(&self) -> usize {
+        (self.number - 1) / 2
+    }
+}
+
+// starting positions from a file with a FEN or EPD per line, EPD operations other than hmvc are ignored.
+// empty lines and lines starting with # are skipped
+pub fn parse_openings(text: &str) -> Result<Vec<(Board, u32)>, String> {
+    let mut openings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let board = Some(fields[..fields.len().min(4)].join(" "))
+            .filter(|_| fields.len() >= 4)
+            .and_then(|position| Board::from_str(&format!("{} 0 1", position)).ok())
+            .ok_or_else(|| format!("Invalid opening on line {}: {}", i + 1, line))?;
+        // the halfmove clock of an EPD is an operation
+        let halfmove_clock = fields
+            .windows(2)
+            .find(|operation| operation[0] == "hmvc")
+            .and_then(|operation| operation[1].trim_end_matches(';').parse().ok())
+            .unwrap_or_else(|| fen_halfmove_clock(line));
+        openings.push((board, halfmove_clock));
+    }
+    Ok(openings)
+}
+
+// play a game without a front end, `players` in the order white, black.
+// players that try an illegal move lose, and draws that can be claimed end the game
+pub fn play_game(
+    players: [&mut dyn Player; 2],
+    start: Board,
+    halfmove_clock: u32,
+    clock: Option<ClockSettings>,
+) -> (GameRecord, Outcome) {
+    let mut players = players;
+    let mut record = GameRecord::new(Game::new_with_board(start), halfmove_clock);
+    for player in players.iter_mut() {
+        player.new_game(&start, halfmove_clock);
+    }
+    let mut clock = clock.map(Clock::new);
+    loop {
+        if let Some(outcome) = record.outcome() {
+            return (record, outcome);
+        }
+        let color = record.game().side_to_move();
+        let player = &mut *players[color.to_index()];
+        if let Some(clock) = &mut clock {
+            if clock.running().is_none() {
+                clock.start(color);
+                player.set_clock(clock.limits(color));
+            }
+        }
+        let action = wait_for_action(player, record.game());
+        let in_time = match (&mut clock, action) {
+            (Some(clock), PlayerAction::Move(_)) => clock.press(),
+            (Some(clock), _) => !clock.flagged(color),
+            (None, _) => true,
+        };
+        if !in_time {
+            let board = record.game().current_position();
+            return (record, Outcome::time_forfeit(&board, color));
+        }
+        match action {
+            PlayerAction::Move(m) => {
+                if !record.make_move(m, player.search_result().cloned()) {
+                    record.resign(color);
+                }
+            }
+            PlayerAction::Resign | PlayerAction::Wait => {
+                record.resign(color);
+            }
+            // the player is asked again for its move if the draw isn't agreed or claimed
+            PlayerAction::OfferDraw => {
+                record.offer_draw(color);
+            }
+            PlayerAction::AcceptDraw => {
+                record.accept_draw();
+            }
+            PlayerAction::DeclineDraw => {
+                record.decline_draw();
+            }
+            PlayerAction::ClaimDraw => {
+                record.claim_draw();
+            }
+            // no takebacks in matches
+            PlayerAction::Undo | PlayerAction::Redo => {}
+        }
+        // bots don't claim draws themselves
+        record.claim_draw();
+    }
+}
+
+// play the match on `concurrency` threads. `create` makes the first (0) or second (1) player for a
+// color, `on_game` is called with every finished game and the score so far, in the order they finish.
+// When it returns false no more games are started, and the games still being played don't count
+pub fn run_match<F>(
+    settings: &MatchSettings,
+    create: F,
+    mut on_game: impl FnMut(&MatchGame, &MatchScore) -> bool,
+) -> Result<MatchScore, String>
+where
+    F: Fn(usize, Color) -> Result<Box<dyn Player>, String> + Sync,
+{
+    let openings = if settings.openings.is_empty() {
+        vec![(Board::default(), 0)]
+    } else {
+        settings.openings.clone()
+    };
+    let next = AtomicUsize::new(0);
+    // set on errors and when the match is stopped
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let mut score = MatchScore::default();
+    thread::scope(|scope| {
+        for _ in 0..settings.concurrency.max(1) {
+            let sender = sender.clone();
+            let (next, stop, create, openings) = (&next, &stop, &create, &openings);
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= settings.games || stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                // the players swap colors every game, and play each opening with both colors
+                let first = if i % 2 == 0 {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                let (start, halfmove_clock) = openings[i / 2 % openings.len()];
+                let game = create(0, first).and_then(|mut first_player| {
+                    let mut second_player = create(1, !first)?;
+                    let players: [&mut dyn Player; 2] = match first {
+                        Color::White => [first_player.as_mut(), second_player.as_mut()],
+                        Color::Black => [second_player.as_mut(), first_player.as_mut()],
+                    };
+                    let (record, outcome) =
+                        play_game(players, start, halfmove_clock, settings.clock);
+                    let error = first_player.error().or(second_player.error());
+                    Ok(MatchGame {
+                        number: i + 1,
+                        first,
+                        record,
+                        outcome,
+                        error: error.map(str::to_string),
+                    })
+                });
+                if game.is_err() {
+                    stop.store(true, Ordering::SeqCst);
+                }
+                if sender.send(game).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        for game in receiver {
+            let game = game?;
+            score.add(game.outcome, game.first);
+            if !on_game(&game, &score) {
+                stop.store(true, Ordering::SeqCst);
+                break;
+            }
+        }
+        Ok(score)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::{BotPlayer, ScriptedPlayer};
+    use crate::Bot;
+    use chess::ChessMove;
+
+    fn script(moves: &[&str]) -> ScriptedPlayer {
+        let moves = moves
+            .iter()
+            .map(|m| ChessMove::from_str(m).unwrap())
+            .collect();
+        ScriptedPlayer::new(moves)
+    }
+
+    // sends all the actions before every move of the scripted player
+    struct Actions {
+        actions: Vec<PlayerAction>,
+        sent: usize,
+        player: ScriptedPlayer,
+    }
+
+    impl Player for Actions {
+        fn name(&self) -> String {
+            self.player.name()
+        }
+
+        fn choose_move(&mut self, game: &Game) -> PlayerAction {
+            if let Some(action) = self.actions.get(self.sent) {
+                self.sent += 1;
+                return *action;
+            }
+            self.sent = 0;
+            self.player.choose_move(game)
+        }
+    }
+
+    #[test]
+    fn openings() {
+        let text = "# openings\n\
+            rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1\n\
+            \n\
+            rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - id \"open game\";\n";
+        let openings = parse_openings(text).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[0].0.side_to_move(), Color::Black);
+        assert_eq!(openings[1].0.side_to_move(), Color::White);
+        // halfmove clocks of FENs and EPDs
+        let text =
+            "4k3/8/8/8/8/8/8/R3K3 w - - 37 80\n4k3/8/8/8/8/8/8/R3K3 w - - hmvc 12; id \"rook\";";
+        let clocks: Vec<u32> = parse_openings(text).unwrap().iter().map(|o| o.1).collect();
+        assert_eq!(clocks, vec![37, 12]);
+        assert!(parse_openings("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq -").is_err());
+        assert!(parse_openings("8/8/8 w").is_err());
+    }
+
+    #[test]
+    fn plays_a_match() {
+        // white mates in one with Rd8, whoever has white wins
+        let settings = MatchSettings {
+            games: 4,
+            openings: vec![(
+                Board::from_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap(),
+                0,
+            )],
+            clock: None,
+            concurrency: 2,
+        };
+        let mut numbers = Vec::new();
+        let create = |_, color| Ok(Box::new(BotPlayer::new(Bot::new(color, 2))) as Box<dyn Player>);
+        let score = run_match(&settings, create, |game, score| {
+            assert_eq!(game.outcome, Outcome::Checkmate(Color::Black));
+            assert_eq!(game.record.ply(), 1);
+            assert_eq!(game.first == Color::White, game.number % 2 == 1);
+            assert_eq!(score.games() as usize, numbers.len() + 1);
+            numbers.push(game.number);
+            true
+        })
+        .unwrap();
+        numbers.sort();
+        assert_eq!(numbers, vec![1, 2, 3, 4]);
+        assert_eq!(score.wins, 2);
+        assert_eq!(score.losses, 2);
+
+        // stopped after the first game
+        let score = run_match(&settings, create, |_, _| false).unwrap();
+        assert_eq!(score.games(), 1);
+
+        let error = run_match(&settings, |_, _| Err("no engine".to_string()), |_, _| true);
+        assert_eq!(error.err(), Some("no engine".to_string()));
+    }
+
+    #[test]
+    fn draw_actions_dont_forfeit() {
+        // white offers a draw, claims one that isn't there and takes back before every move
+        let mut white = Actions {
+            actions: vec![
+                PlayerAction::OfferDraw,
+                PlayerAction::ClaimDraw,
+                PlayerAction::Undo,
+            ],
+            sent: 0,
+            player: script(&["f2f3", "g2g4"]),
+        };
+        let mut black = script(&["e7e5", "d8h4"]);
+        let (record, outcome) = play_game([&mut white, &mut black], Board::default(), 0, None);
+        assert_eq!(outcome, Outcome::Checkmate(Color::White));
+        assert_eq!(record.ply(), 4);
+    }
+
+    #[test]
+    fn claims_draws() {
+        let mut white = BotPlayer::new(Bot::new(Color::White, 1));
+        let mut black = BotPlayer::new(Bot::new(Color::Black, 1));
+        let start = Board::from_str("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let clock = "60+1".parse().ok();
+        let (record, outcome) = play_game([&mut white, &mut black], start, 0, clock);
+        // the game ends on the board, by mate or a draw that could be claimed, and not on time
+        assert!(record.ply() > 0);
+        assert!(!matches!(
+            outcome,
+            Outcome::Timeout(_) | Outcome::TimeoutDraw(_) | Outcome::Resignation(_)
+        ));
+
+        // the 50 moves count on from the halfmove clock of the opening
+        let mut white = script(&["a1b1", "b1c1", "c1d1", "d1e1"]);
+        let mut black = script(&["a8b8", "b8c8", "c8d8", "d8e8"]);
+        let start = Board::from_str("r6k/8/8/8/8/8/8/R6K w - - 92 1").unwrap();
+        let (record, outcome) = play_game([&mut white, &mut black], start, 92, None);
+        assert_eq!(outcome, Outcome::FiftyMoves);
+        assert_eq!(record.ply(), 8);
+    }
+}
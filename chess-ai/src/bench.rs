@@ -0,0 +1,39 @@
+// fixed set of positions to compare the node counts of different search configurations
+use chess::Board;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::Bot;
+
+// opening, middlegame and endgame positions
+pub const BENCH_POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "2rq1rk1/pp1bppbp/3p1np1/8/3NP3/1BN1BP2/PPPQ2PP/2KR3R b - - 0 12",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BenchResult {
+    pub nodes: u64,
+    pub qnodes: u64,
+    pub time: Duration,
+}
+
+// search every benchmark position with a fresh transposition table, the bot's depth and limits are used as configured
+pub fn run_bench(bot: &mut Bot) -> BenchResult {
+    let mut total = BenchResult::default();
+    for fen in BENCH_POSITIONS.iter() {
+        let board = Board::from_str(fen).expect("Valid FEN");
+        bot.new_game();
+        let result = bot.get_move(board);
+        total.nodes += result.nodes;
+        total.qnodes += result.qnodes;
+        total.time += result.time;
+    }
+    total
+}
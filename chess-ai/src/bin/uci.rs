@@ -0,0 +1,6 @@
+// UCI engine, for chess GUIs and tournament managers
+use std::io;
+
+fn main() {
+    chess_ai::uci::run(io::stdin().lock(), io::stdout());
+}
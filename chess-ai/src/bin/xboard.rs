@@ -0,0 +1,6 @@
+// XBoard / CECP engine, for XBoard and other tools speaking the chess engine communication protocol
+use std::io;
+
+fn main() {
+    chess_ai::xboard::run(io::stdin().lock(), io::stdout());
+}
@@ -0,0 +1,291 @@
+// chess clocks for games between players, the bot's own budget per move is in engine::SearchLimits
+use chess::Color;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::engine::SearchLimits;
+
+// what a player gets back for each move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Increment {
+    None,
+    // added after every move
+    Fischer(Duration),
+    // the time used for the move is given back, up to the delay
+    Bronstein(Duration),
+    // simple delay, the clock only starts running after the delay
+    Delay(Duration),
+}
+
+// time control of a game, written as [moves/]base[+increment|d<delay>|b<delay>] in seconds,
+// e.g. 300 (sudden death), 180+2 (fischer), 300d5 (simple delay), 300b5 (bronstein), 40/5400+30
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockSettings {
+    pub base: Duration,
+    // the base time is added again after this many moves, None for sudden death
+    pub moves_per_session: Option<u32>,
+    pub increment: Increment,
+}
+
+fn parse_seconds(s: &str) -> Option<Duration> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+impl FromStr for ClockSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ClockSettings, String> {
+        let invalid = || format!("Invalid time control: {}", s);
+        let (moves_per_session, rest) = match s.split_once('/') {
+            Some((moves, rest)) => (
+                Some(moves.parse().ok().filter(|m| *m > 0).ok_or_else(invalid)?),
+                rest,
+            ),
+            None => (None, s),
+        };
+        let (base, increment) = match rest.find(['+', 'd', 'b']) {
+            Some(i) => {
+                let time = parse_seconds(&rest[i + 1..]).ok_or_else(invalid)?;
+                let increment = match &rest[i..i + 1] {
+                    "+" => Increment::Fischer(time),
+                    "d" => Increment::Delay(time),
+                    _ => Increment::Bronstein(time),
+                };
+                (&rest[..i], increment)
+            }
+            None => (rest, Increment::None),
+        };
+        let base = parse_seconds(base)
+            .filter(|b| !b.is_zero())
+            .ok_or_else(invalid)?;
+        Ok(ClockSettings {
+            base,
+            moves_per_session,
+            increment,
+        })
+    }
+}
+
+impl fmt::Display for ClockSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(moves) = self.moves_per_session {
+            write!(f, "{}/", moves)?;
+        }
+        write!(f, "{}", self.base.as_secs_f64())?;
+        match self.increment {
+            Increment::None => Ok(()),
+            Increment::Fischer(t) => write!(f, "+{}", t.as_secs_f64()),
+            Increment::Delay(t) => write!(f, "d{}", t.as_secs_f64()),
+            Increment::Bronstein(t) => write!(f, "b{}", t.as_secs_f64()),
+        }
+    }
+}
+
+// time on a clock, like 1:05:03, 4:59 or 0:09.4 in the last seconds
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else if secs >= 20 {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("0:{:02}.{}", secs, time.subsec_millis() / 100)
+    }
+}
+
+// the clocks of both players, only the clock of the side to move runs.
+// the _at variants take the current time, for tests
+#[derive(Clone, Debug)]
+pub struct Clock {
+    settings: ClockSettings,
+    remaining: [Duration; 2],
+    // moves played by each side
+    moves: [u32; 2],
+    // the running clock, and since when it runs
+    running: Option<(Color, Instant)>,
+}
+
+impl Clock {
+    pub fn new(settings: ClockSettings) -> Clock {
+        Clock {
+            settings,
+            remaining: [settings.base; 2],
+            moves: [0; 2],
+            running: None,
+        }
+    }
+
+    pub fn settings(&self) -> &ClockSettings {
+        &self.settings
+    }
+
+    // the side whose clock is running
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+
+    // start the clock of the side to move
+    pub fn start(&mut self, color: Color) {
+        self.start_at(color, Instant::now());
+    }
+
+    pub fn start_at(&mut self, color: Color, now: Instant) {
+        self.running = Some((color, now));
+    }
+
+    // time the player has used up of a move that took `elapsed`
+    fn charged(&self, elapsed: Duration) -> Duration {
+        match self.settings.increment {
+            Increment::Delay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        }
+    }
+
+    pub fn remaining(&self, color: Color) -> Duration {
+        self.remaining_at(color, Instant::now())
+    }
+
+    pub fn remaining_at(&self, color: Color, now: Instant) -> Duration {
+        let remaining = self.remaining[color.to_index()];
+        match self.running {
+            Some((running, since)) if running == color => {
+                remaining.saturating_sub(self.charged(now.saturating_duration_since(since)))
+            }
+            _ => remaining,
+        }
+    }
+
+    // whether the flag of `color` has fallen
+    pub fn flagged(&self, color: Color) -> bool {
+        self.remaining(color).is_zero()
+    }
+
+    // stop the running clock after a move, false if the time ran out before the move was made
+    pub fn press(&mut self) -> bool {
+        self.press_at(Instant::now())
+    }
+
+    pub fn press_at(&mut self, now: Instant) -> bool {
+        let (color, since) = match self.running.take() {
+            Some(running) => running,
+            None => return true,
+        };
+        let elapsed = now.saturating_duration_since(since);
+        let i = color.to_index();
+        let charged = self.charged(elapsed);
+        if charged >= self.remaining[i] {
+            self.remaining[i] = Duration::ZERO;
+            return false;
+        }
+        self.remaining[i] -= charged;
+        self.remaining[i] += match self.settings.increment {
+            Increment::Fischer(increment) => increment,
+            Increment::Bronstein(delay) => elapsed.min(delay),
+            _ => Duration::ZERO,
+        };
+        self.moves[i] += 1;
+        if let Some(moves) = self.settings.moves_per_session {
+            if self.moves[i].is_multiple_of(moves) {
+                self.remaining[i] += self.settings.base;
+            }
+        }
+        true
+    }
+
+    // search limits for a player whose budget comes from its clock
+    pub fn limits(&self, color: Color) -> SearchLimits {
+        let increment = match self.settings.increment {
+            Increment::None => Duration::ZERO,
+            Increment::Fischer(t) | Increment::Bronstein(t) | Increment::Delay(t) => t,
+        };
+        SearchLimits {
+            time_left: Some(self.remaining(color)),
+            increment,
+            moves_to_go: self
+                .settings
+                .moves_per_session
+                .map(|m| m - self.moves[color.to_index()] % m),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    // play a move taking `time` with the clock of `color`
+    fn play(clock: &mut Clock, color: Color, now: &mut Instant, time: u64) -> bool {
+        clock.start_at(color, *now);
+        *now += secs(time);
+        clock.press_at(*now)
+    }
+
+    #[test]
+    fn settings() {
+        for s in ["300", "180+2", "300d5", "300b5", "40/5400+30", "60+0.5"] {
+            let settings = ClockSettings::from_str(s).unwrap();
+            assert_eq!(settings.to_string(), s);
+        }
+        let settings = ClockSettings::from_str("40/5400+30").unwrap();
+        assert_eq!(settings.base, secs(5400));
+        assert_eq!(settings.moves_per_session, Some(40));
+        assert_eq!(settings.increment, Increment::Fischer(secs(30)));
+        for s in ["", "0", "five", "0/300", "300+", "300x2", "-1"] {
+            assert!(ClockSettings::from_str(s).is_err(), "{}", s);
+        }
+        assert_eq!(format_time(secs(3723)), "1:02:03");
+        assert_eq!(format_time(secs(299)), "4:59");
+        assert_eq!(format_time(Duration::from_millis(9470)), "0:09.4");
+    }
+
+    #[test]
+    fn increments() {
+        let mut now = Instant::now();
+        let mut clock = Clock::new(ClockSettings::from_str("60+2").unwrap());
+        assert!(play(&mut clock, Color::White, &mut now, 10));
+        assert_eq!(clock.remaining(Color::White), secs(52));
+        assert_eq!(clock.remaining(Color::Black), secs(60));
+
+        // bronstein gives back the time used, up to the delay
+        let mut clock = Clock::new(ClockSettings::from_str("60b5").unwrap());
+        assert!(play(&mut clock, Color::White, &mut now, 3));
+        assert_eq!(clock.remaining(Color::White), secs(60));
+        assert!(play(&mut clock, Color::White, &mut now, 10));
+        assert_eq!(clock.remaining(Color::White), secs(55));
+
+        // simple delay, the clock only runs after the delay
+        let mut clock = Clock::new(ClockSettings::from_str("60d5").unwrap());
+        clock.start_at(Color::Black, now);
+        assert_eq!(clock.running(), Some(Color::Black));
+        assert_eq!(clock.remaining_at(Color::Black, now + secs(4)), secs(60));
+        assert_eq!(clock.remaining_at(Color::Black, now + secs(8)), secs(57));
+        assert!(play(&mut clock, Color::Black, &mut now, 8));
+        assert_eq!(clock.remaining(Color::Black), secs(57));
+    }
+
+    #[test]
+    fn sessions_and_flags() {
+        let mut now = Instant::now();
+        let mut clock = Clock::new(ClockSettings::from_str("2/60").unwrap());
+        assert_eq!(clock.limits(Color::White).moves_to_go, Some(2));
+        assert!(play(&mut clock, Color::White, &mut now, 20));
+        assert_eq!(clock.limits(Color::White).moves_to_go, Some(1));
+        assert!(play(&mut clock, Color::White, &mut now, 20));
+        // the next session adds the base time
+        assert_eq!(clock.remaining(Color::White), secs(80));
+        assert_eq!(clock.limits(Color::White).time_left, Some(secs(80)));
+
+        assert!(!play(&mut clock, Color::Black, &mut now, 61));
+        assert!(clock.flagged(Color::Black));
+        assert!(!clock.flagged(Color::White));
+    }
+}
@@ -0,0 +1,380 @@
+// bookkeeping shared by the engine protocols (UCI, XBoard): the position of the game, the time control
+// and the search running in the background while the protocol keeps reading commands
+use chess::{Board, ChessMove, Color, MoveGen};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::history::fen_halfmove_clock;
+use crate::{Bot, History, SearchLog, SearchResult, SearchStopper};
+
+// number of moves the remaining time is divided into when the time control doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// time kept in reserve for the communication with the GUI
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+// a game as the engine sees it: the starting position and the moves played since
+#[derive(Clone, Debug)]
+pub struct Position {
+    start: Board,
+    start_halfmove_clock: u32,
+    moves: Vec<ChessMove>,
+    board: Board,
+    history: History,
+}
+
+impl Position {
+    pub fn new(start: Board, halfmove_clock: u32) -> Position {
+        Position {
+            start,
+            start_halfmove_clock: halfmove_clock,
+            moves: Vec::new(),
+            board: start,
+            history: History::new(&start, halfmove_clock),
+        }
+    }
+
+    // the halfmove clock of the FEN is kept, the chess crate ignores it
+    pub fn from_fen(fen: &str) -> Result<Position, String> {
+        let board = Board::from_str(fen).map_err(|_| format!("Invalid FEN: {}", fen))?;
+        Ok(Position::new(board, fen_halfmove_clock(fen)))
+    }
+
+    pub fn start(&self) -> Board {
+        self.start
+    }
+
+    pub fn board(&self) -> Board {
+        self.board
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn moves(&self) -> &[ChessMove] {
+        &self.moves
+    }
+
+    // parse a move in coordinate notation (e2e4, e7e8q) and check that it is legal
+    pub fn parse_move(&self, s: &str) -> Result<ChessMove, String> {
+        match ChessMove::from_str(s) {
+            Ok(m) if self.board.legal(m) => Ok(m),
+            _ => Err(format!("Illegal move: {}", s)),
+        }
+    }
+
+    pub fn make_move(&mut self, m: ChessMove) -> Result<(), String> {
+        if !self.board.legal(m) {
+            return Err(format!("Illegal move: {}", m));
+        }
+        self.history.push(&self.board, m);
+        self.board = self.board.make_move_new(m);
+        self.moves.push(m);
+        Ok(())
+    }
+
+    // take back the last move
+    pub fn undo(&mut self) -> Option<ChessMove> {
+        let m = self.moves.pop()?;
+        let moves = std::mem::take(&mut self.moves);
+        *self = Position::new(self.start, self.start_halfmove_clock);
+        for m in moves {
+            self.make_move(m).expect("Moves were legal before");
+        }
+        Some(m)
+    }
+
+    // whether the side to move has no legal moves
+    pub fn is_game_over(&self) -> bool {
+        MoveGen::new_legal(&self.board).len() == 0
+    }
+}
+
+// FEN of a board. The chess crate writes the square of the pawn that can be captured en passant
+// instead of the square behind it, which other programs don't understand
+pub fn to_fen(board: &Board) -> String {
+    let fen = board.to_string();
+    match board.en_passant() {
+        Some(pawn) => {
+            let target = match board.side_to_move() {
+                Color::White => pawn.up(),
+                Color::Black => pawn.down(),
+            }
+            .expect("En passant pawns aren't on the edge of the board");
+            let mut fields: Vec<String> = fen.split(' ').map(|f| f.to_string()).collect();
+            fields[3] = target.to_string();
+            fields.join(" ")
+        }
+        None => fen,
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new(Board::default(), 0)
+    }
+}
+
+// how long the engine may search for its next move
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    // fixed time for this move
+    pub move_time: Option<Duration>,
+    // remaining time on the engine's clock
+    pub time_left: Option<Duration>,
+    pub increment: Duration,
+    // moves until the next time control, None for sudden death
+    pub moves_to_go: Option<u32>,
+    // search until told to stop
+    pub infinite: bool,
+}
+
+impl SearchLimits {
+    // time to spend on the next move, None if the search isn't limited by time
+    pub fn time_budget(&self) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(t) = self.move_time {
+            return Some(
+                t.saturating_sub(MOVE_OVERHEAD)
+                    .max(Duration::from_millis(1)),
+            );
+        }
+        let time_left = self.time_left?;
+        let moves = self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let budget = time_left / moves + self.increment * 3 / 4;
+        // never use more than what is left on the clock
+        Some(
+            budget
+                .min(time_left.saturating_sub(MOVE_OVERHEAD))
+                .max(Duration::from_millis(1)),
+        )
+    }
+
+    // configure the bot for the next search, `default_depth` is used if the limits don't set a depth
+    pub fn apply(&self, bot: &mut Bot, default_depth: u8) {
+        bot.set_depth(self.depth.unwrap_or(default_depth));
+        bot.set_time_limit(self.time_budget());
+        bot.set_node_limit(self.nodes);
+    }
+}
+
+// time control of a game: moves per session (None for the whole game), base time and increment per move,
+// or a fixed time per move. the engine's clock is reported separately by the GUI
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimeControl {
+    pub moves_per_session: Option<u32>,
+    pub base: Duration,
+    pub increment: Duration,
+    pub move_time: Option<Duration>,
+    pub depth: Option<u8>,
+}
+
+impl TimeControl {
+    // limits for the next move, given the time left on the engine's clock and the number of moves the engine
+    // has played in this game
+    pub fn limits(&self, time_left: Option<Duration>, moves_played: u32) -> SearchLimits {
+        SearchLimits {
+            depth: self.depth,
+            move_time: self.move_time,
+            time_left: if self.move_time.is_some() {
+                None
+            } else {
+                time_left
+            },
+            increment: self.increment,
+            moves_to_go: self.moves_per_session.map(|m| m - moves_played % m.max(1)),
+            ..Default::default()
+        }
+    }
+}
+
+pub type Output<W> = Arc<Mutex<W>>;
+
+// write a line to the GUI
+pub fn send<W: Write>(out: &Mutex<W>, line: &str) {
+    let mut out = out.lock().expect("Output lock poisoned");
+    // a GUI that closed the pipe can't be told about errors anyway
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+// called with the result once a search is done
+pub type SearchDone = Box<dyn FnOnce(&SearchResult) + Send>;
+
+// owns the bot, and searches with it on a background thread
+pub struct Engine {
+    // None while searching
+    bot: Option<Bot>,
+    search: Option<RunningSearch>,
+}
+
+struct RunningSearch {
+    stopper: SearchStopper,
+    thread: JoinHandle<(Bot, SearchResult)>,
+    infinite: bool,
+}
+
+impl Engine {
+    pub fn new(bot: Bot) -> Engine {
+        Engine {
+            bot: Some(bot),
+            search: None,
+        }
+    }
+
+    // the bot, a running search is stopped first
+    pub fn bot(&mut self) -> &mut Bot {
+        self.finish(true);
+        self.bot.as_mut().expect("No search is running")
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    // whether the running search is infinite
+    pub fn is_infinite(&self) -> bool {
+        self.search.as_ref().is_some_and(|s| s.infinite)
+    }
+
+    // whether a search has been started and is done now, its result can be collected with finish()
+    pub fn is_done(&self) -> bool {
+        self.search.as_ref().is_some_and(|s| s.thread.is_finished())
+    }
+
+    // start searching the position, a running search is stopped first.
+    // `log` receives every completed iteration and `done` the final result, both on the search thread.
+    // an infinite search only calls `done` after it has been stopped
+    pub fn start(
+        &mut self,
+        position: &Position,
+        limits: SearchLimits,
+        default_depth: u8,
+        log: Option<SearchLog>,
+        done: SearchDone,
+    ) {
+        let bot = self.bot();
+        limits.apply(bot, default_depth);
+        bot.set_log(log);
+        let bot = self.bot.take().expect("No search is running");
+
+        let handle = bot.start_search(position.board(), position.history().clone());
+        let stopper = handle.stopper();
+        let waiter_stopper = stopper.clone();
+        let infinite = limits.infinite;
+        let thread = thread::spawn(move || {
+            let (bot, result) = handle.wait();
+            while infinite && !waiter_stopper.is_stopped() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            done(&result);
+            (bot, result)
+        });
+        self.search = Some(RunningSearch {
+            stopper,
+            thread,
+            infinite,
+        });
+    }
+
+    // ask the running search to stop, without waiting for it
+    pub fn stop(&self) {
+        if let Some(search) = &self.search {
+            search.stopper.stop();
+        }
+    }
+
+    // wait for the running search to end, optionally asking it to stop first.
+    // returns the result, or None if no search was running
+    pub fn finish(&mut self, stop: bool) -> Option<SearchResult> {
+        let search = self.search.take()?;
+        if stop {
+            search.stopper.stop();
+        }
+        let (bot, result) = search.thread.join().expect("Search thread panicked");
+        self.bot = Some(bot);
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fen_with_en_passant() {
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(to_fen(&Board::from_str(fen).unwrap()), fen);
+        let fen = "4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 1";
+        assert_eq!(to_fen(&Board::from_str(fen).unwrap()), fen);
+        let start = Board::default();
+        assert_eq!(to_fen(&start), start.to_string());
+    }
+
+    #[test]
+    fn position_moves_and_undo() {
+        let mut position =
+            Position::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 12 40").expect("Valid FEN");
+        assert_eq!(position.history().halfmove_clock(), 12);
+        let m = position.parse_move("e1g1").expect("Castling is legal");
+        position.make_move(m).unwrap();
+        assert_eq!(position.history().halfmove_clock(), 13);
+        assert!(position.parse_move("e1g1").is_err());
+        assert!(position.parse_move("xyz").is_err());
+
+        assert_eq!(position.undo(), Some(m));
+        assert_eq!(
+            position.board(),
+            Board::from_str("4k3/8/8/8/8/8/8/4K2R w K - 12 40").unwrap()
+        );
+        assert_eq!(position.history().halfmove_clock(), 12);
+        assert_eq!(position.undo(), None);
+    }
+
+    #[test]
+    fn time_budget() {
+        let limits = SearchLimits {
+            time_left: Some(Duration::from_secs(60)),
+            increment: Duration::from_secs(1),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(), Some(Duration::from_millis(2750)));
+
+        // the last move before the time control may use almost all of the time
+        let limits = SearchLimits {
+            time_left: Some(Duration::from_secs(1)),
+            moves_to_go: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(), Some(Duration::from_millis(950)));
+
+        let limits = SearchLimits {
+            move_time: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget(), Some(Duration::from_millis(950)));
+        assert_eq!(SearchLimits::default().time_budget(), None);
+    }
+
+    #[test]
+    fn moves_per_session() {
+        // 40 moves in 5 minutes
+        let tc = TimeControl {
+            moves_per_session: Some(40),
+            base: Duration::from_secs(300),
+            ..Default::default()
+        };
+        let limits = tc.limits(Some(Duration::from_secs(100)), 0);
+        assert_eq!(limits.moves_to_go, Some(40));
+        assert_eq!(limits.time_left, Some(Duration::from_secs(100)));
+        assert_eq!(tc.limits(None, 39).moves_to_go, Some(1));
+        assert_eq!(tc.limits(None, 40).moves_to_go, Some(40));
+    }
+}
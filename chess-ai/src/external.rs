@@ -0,0 +1,442 @@
+// drives an external UCI engine over pipes, so other engines can play against the bot
+use chess::{ChessMove, Color};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::engine::{to_fen, Position, SearchLimits};
+use crate::{Score, SearchResult};
+
+// time per move of engines configured without limits, they never answer `go infinite` by themselves
+pub const DEFAULT_MOVE_TIME: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub path: PathBuf,
+    // UCI options, sent with setoption after the handshake
+    pub options: Vec<(String, String)>,
+    // limits per move, DEFAULT_MOVE_TIME if neither is set
+    pub move_time: Option<Duration>,
+    pub depth: Option<u8>,
+}
+
+pub struct ExternalEngine {
+    child: Child,
+    // shared with EngineStop
+    stdin: Arc<Mutex<ChildStdin>>,
+    stopped: Arc<AtomicBool>,
+    stdout: BufReader<ChildStdout>,
+    // name the engine reported with `id name`
+    name: String,
+    move_time: Option<Duration>,
+    depth: Option<u8>,
+}
+
+// stops the search of an engine while get_move waits for its move on another thread
+#[derive(Clone)]
+pub struct EngineStop {
+    stdin: Arc<Mutex<ChildStdin>>,
+    // a stop before the search started is sent again after `go`
+    stopped: Arc<AtomicBool>,
+}
+
+impl EngineStop {
+    // the engine answers with its best move so far
+    pub fn stop(&self) -> io::Result<()> {
+        let mut stdin = self.stdin.lock().unwrap();
+        self.stopped.store(true, Ordering::SeqCst);
+        send(&mut stdin, "stop")
+    }
+}
+
+fn send(stdin: &mut ChildStdin, line: &str) -> io::Result<()> {
+    writeln!(stdin, "{}", line)?;
+    stdin.flush()
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl ExternalEngine {
+    // spawn the engine, do the UCI handshake and set the options
+    pub fn start(config: &EngineConfig) -> io::Result<ExternalEngine> {
+        let mut child = Command::new(&config.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = Arc::new(Mutex::new(child.stdin.take().expect("stdin is piped")));
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut engine = ExternalEngine {
+            child,
+            stdin,
+            stopped: Arc::new(AtomicBool::new(false)),
+            stdout,
+            name: config.path.display().to_string(),
+            move_time: config.move_time,
+            depth: config.depth,
+        };
+
+        engine.send("uci")?;
+        loop {
+            let line = engine.read_line()?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        for (name, value) in &config.options {
+            engine.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        engine.wait_ready()?;
+        Ok(engine)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn stopper(&self) -> EngineStop {
+        EngineStop {
+            stdin: Arc::clone(&self.stdin),
+            stopped: Arc::clone(&self.stopped),
+        }
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.wait_ready()
+    }
+
+    // let the engine search the current position of the game. With a clock, the engine manages
+    // its time itself instead of using the configured time per move
+    pub fn get_move(
+        &mut self,
+        position: &Position,
+        clock: Option<&SearchLimits>,
+    ) -> io::Result<SearchResult> {
+        let mut command = format!("position fen {}", to_fen(&position.start()));
+        if !position.moves().is_empty() {
+            command.push_str(" moves");
+            for m in position.moves() {
+                command.push_str(&format!(" {}", m));
+            }
+        }
+        self.send(&command)?;
+
+        let mut go = "go".to_string();
+        if let Some(depth) = self.depth {
+            go.push_str(&format!(" depth {}", depth));
+        }
+        let side = match position.board().side_to_move() {
+            Color::White => 'w',
+            Color::Black => 'b',
+        };
+        match clock {
+            Some(SearchLimits {
+                time_left: Some(time_left),
+                increment,
+                moves_to_go,
+                ..
+            }) => {
+                go.push_str(&format!(
+                    " {}time {} {}inc {}",
+                    side,
+                    time_left.as_millis(),
+                    side,
+                    increment.as_millis()
+                ));
+                if let Some(moves) = moves_to_go {
+                    go.push_str(&format!(" movestogo {}", moves));
+                }
+            }
+            _ => {
+                let move_time = match (self.move_time, self.depth) {
+                    (None, None) => Some(DEFAULT_MOVE_TIME),
+                    (move_time, _) => move_time,
+                };
+                if let Some(t) = move_time {
+                    go.push_str(&format!(" movetime {}", t.as_millis()));
+                }
+            }
+        }
+        let start = Instant::now();
+        {
+            let mut stdin = self.stdin.lock().unwrap();
+            send(&mut stdin, &go)?;
+            if self.stopped.load(Ordering::SeqCst) {
+                send(&mut stdin, "stop")?;
+            }
+        }
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: Score::Centipawns(0),
+            depth: 0,
+            nodes: 0,
+            qnodes: 0,
+            time: Duration::ZERO,
+            pv: Vec::new(),
+        };
+        loop {
+            let line = self.read_line()?;
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("info") => parse_info(&mut result, tokens),
+                Some("bestmove") => {
+                    let m = tokens.next().unwrap_or("(none)");
+                    result.best_move = match m {
+                        "(none)" | "0000" => None,
+                        _ => Some(
+                            position
+                                .parse_move(m)
+                                .map_err(|e| protocol_error(format!("{}: {}", self.name, e)))?,
+                        ),
+                    };
+                    result.time = start.elapsed();
+                    if result.pv.first() != result.best_move.as_ref() {
+                        result.pv = result.best_move.into_iter().collect();
+                    }
+                    self.stopped.store(false, Ordering::SeqCst);
+                    return Ok(result);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        send(&mut self.stdin.lock().unwrap(), line)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(protocol_error(format!("{} quit unexpectedly", self.name)));
+        }
+        Ok(line)
+    }
+
+    fn wait_ready(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        while self.read_line()?.trim() != "readyok" {}
+        Ok(())
+    }
+}
+
+impl Drop for ExternalEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // give the engine a moment to exit on its own
+        for _ in 0..50 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// read depth, score, nodes and principal variation from an info line
+fn parse_info<'a>(result: &mut SearchResult, mut tokens: impl Iterator<Item = &'a str>) {
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => {
+                if let Some(d) = tokens.next().and_then(|d| d.parse().ok()) {
+                    result.depth = d;
+                }
+            }
+            "nodes" => {
+                if let Some(n) = tokens.next().and_then(|n| n.parse().ok()) {
+                    result.nodes = n;
+                }
+            }
+            "score" => {
+                let kind = tokens.next();
+                let value = tokens.next().and_then(|v| v.parse().ok());
+                match (kind, value) {
+                    (Some("cp"), Some(cp)) => result.score = Score::Centipawns(cp),
+                    (Some("mate"), Some(n)) => result.score = Score::Mate(n),
+                    _ => {}
+                }
+            }
+            "pv" => {
+                result.pv = tokens
+                    .by_ref()
+                    .map_while(|m| ChessMove::from_str(m).ok())
+                    .collect();
+            }
+            // the rest of the line is free text
+            "string" => return,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::{wait_for_action, EnginePlayer, Player, PlayerAction};
+    use chess::{Board, Game, Square};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // a fake engine: answers the handshake, logs the commands it receives and always plays the same move
+    fn fake_engine(name: &str, best_move: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("chess-ai-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("commands.log");
+        let script = dir.join("engine.sh");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\n\
+                 while read line; do\n\
+                   echo \"$line\" >> {log}\n\
+                   case \"$line\" in\n\
+                     uci) echo 'id name Fake Engine'; echo 'option name Hash type spin'; echo uciok;;\n\
+                     isready) echo readyok;;\n\
+                     'go movetime 60000') searching=1;;\n\
+                     stop) [ -n \"$searching\" ] && echo 'bestmove {m}'; searching=;;\n\
+                     go*) echo 'info depth 3 score cp -25 nodes 1234 pv {m} e7e5'; echo 'bestmove {m}';;\n\
+                     quit) exit 0;;\n\
+                   esac\n\
+                 done\n",
+                log = log.display(),
+                m = best_move
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        (script, log)
+    }
+
+    #[test]
+    fn plays_against_fake_engine() {
+        let (path, log) = fake_engine("plays", "d2d4");
+        let config = EngineConfig {
+            path,
+            options: vec![("Hash".to_string(), "32".to_string())],
+            move_time: Some(Duration::from_millis(100)),
+            depth: None,
+        };
+        let mut engine = ExternalEngine::start(&config).expect("Fake engine starts");
+        assert_eq!(engine.name(), "Fake Engine");
+        engine.new_game().unwrap();
+
+        let mut position = Position::default();
+        let e4 = position.parse_move("e2e4").unwrap();
+        position.make_move(e4).unwrap();
+        position
+            .make_move(position.parse_move("e7e5").unwrap())
+            .unwrap();
+        let result = engine.get_move(&position, None).unwrap();
+        assert_eq!(
+            result.best_move,
+            Some(ChessMove::new(Square::D2, Square::D4, None))
+        );
+        assert_eq!(result.score, Score::Centipawns(-25));
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.nodes, 1234);
+        // with a clock, the engine gets the time left instead of the time per move
+        let clock = SearchLimits {
+            time_left: Some(Duration::from_secs(60)),
+            increment: Duration::from_secs(2),
+            moves_to_go: Some(10),
+            ..Default::default()
+        };
+        engine.get_move(&position, Some(&clock)).unwrap();
+        drop(engine);
+
+        let commands = fs::read_to_string(log).unwrap();
+        let commands: Vec<&str> = commands.lines().collect();
+        assert_eq!(
+            commands,
+            vec![
+                "uci",
+                "setoption name Hash value 32",
+                "isready",
+                "ucinewgame",
+                "isready",
+                "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 moves e2e4 e7e5",
+                "go movetime 100",
+                "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 moves e2e4 e7e5",
+                "go wtime 60000 winc 2000 movestogo 10",
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn default_move_time() {
+        let (path, log) = fake_engine("default", "e2e4");
+        let config = EngineConfig {
+            path,
+            ..Default::default()
+        };
+        let mut engine = ExternalEngine::start(&config).expect("Fake engine starts");
+        assert!(engine.get_move(&Position::default(), None).is_ok());
+        drop(engine);
+        let commands = fs::read_to_string(log).unwrap();
+        assert!(commands.lines().any(|line| line == "go movetime 1000"));
+    }
+
+    #[test]
+    fn stops_a_search() {
+        let (path, _) = fake_engine("stop", "e2e4");
+        let config = EngineConfig {
+            path,
+            move_time: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let engine = ExternalEngine::start(&config).expect("Fake engine starts");
+        let mut player = EnginePlayer::new(engine);
+        let game = chess::Game::new();
+        assert_eq!(player.choose_move(&game), PlayerAction::Wait);
+        player.stop();
+        assert_eq!(
+            wait_for_action(&mut player, &game),
+            PlayerAction::Move(ChessMove::new(Square::E2, Square::E4, None))
+        );
+    }
+
+    #[test]
+    fn illegal_move_is_an_error() {
+        let (path, _) = fake_engine("illegal", "e2e5");
+        let config = EngineConfig {
+            path,
+            depth: Some(1),
+            ..Default::default()
+        };
+        let mut engine = ExternalEngine::start(&config).expect("Fake engine starts");
+        assert!(engine.get_move(&Position::default(), None).is_err());
+
+        // players resign and keep the error for the front end
+        let mut player = EnginePlayer::new(engine);
+        player.new_game(&Board::default(), 0);
+        assert_eq!(
+            wait_for_action(&mut player, &Game::new()),
+            PlayerAction::Resign
+        );
+        assert!(player
+            .error()
+            .is_some_and(|e| e.starts_with("Fake Engine failed")));
+    }
+
+    #[test]
+    fn missing_engine() {
+        let config = EngineConfig {
+            path: PathBuf::from("/nonexistent/engine"),
+            ..Default::default()
+        };
+        assert!(ExternalEngine::start(&config).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+// search running on a background thread
+use chess::Board;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::{Bot, History, SearchResult};
+
+pub struct SearchHandle {
+    thread: JoinHandle<(Bot, SearchResult)>,
+    progress: Arc<Mutex<Option<SearchResult>>>,
+    stopper: SearchStopper,
+}
+
+// stops the search of a bot from another thread
+#[derive(Clone)]
+pub struct SearchStopper {
+    abort: Arc<AtomicBool>,
+}
+
+impl SearchStopper {
+    pub fn stop(&self) {
+        self.abort.store(true, Ordering::Relaxed);
+    }
+
+    // whether a stop has been requested since the search started
+    pub fn is_stopped(&self) -> bool {
+        self.abort.load(Ordering::Relaxed)
+    }
+}
+
+impl Bot {
+    // start searching the position on a new thread, the bot is handed back when the search is done
+    pub fn start_search(mut self, board: Board, history: History) -> SearchHandle {
+        // reset before the thread starts, so a stop requested right away isn't lost
+        self.abort.store(false, Ordering::Relaxed);
+        let progress = Arc::new(Mutex::new(None));
+        self.progress = Some(Arc::clone(&progress));
+        let stopper = SearchStopper {
+            abort: Arc::clone(&self.abort),
+        };
+        let thread = thread::spawn(move || {
+            let result = self.run(board, &history);
+            self.progress = None;
+            (self, result)
+        });
+        SearchHandle {
+            thread,
+            progress,
+            stopper,
+        }
+    }
+}
+
+impl SearchHandle {
+    // result of the last completed iteration, None if the first one isn't done yet
+    pub fn progress(&self) -> Option<SearchResult> {
+        self.progress
+            .lock()
+            .expect("Progress lock poisoned")
+            .clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // ask the search to stop, wait() then returns the best move found so far
+    pub fn stop(&self) {
+        self.stopper.stop();
+    }
+
+    // can be moved to another thread to stop the search from there
+    pub fn stopper(&self) -> SearchStopper {
+        self.stopper.clone()
+    }
+
+    // block until the search is done
+    pub fn wait(self) -> (Bot, SearchResult) {
+        self.thread.join().expect("Search thread panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Color, MoveGen};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn stop_returns_best_move_so_far() {
+        let board = Board::default();
+        let bot = Bot::new(Color::White, 100);
+        let handle = bot.start_search(board, History::new(&board, 0));
+        // wait for the first iterations to complete
+        let start = Instant::now();
+        while handle.progress().is_none_or(|r| r.depth < 3) {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!handle.is_finished());
+        handle.stop();
+        let (mut bot, result) = handle.wait();
+        assert!(result.depth >= 3);
+        assert!(board.legal(result.best_move.unwrap()));
+
+        // the bot can be used again
+        bot.set_node_limit(Some(1000));
+        assert!(bot.get_move(board).best_move.is_some());
+    }
+
+    #[test]
+    fn stop_before_first_iteration() {
+        let board = Board::default();
+        let handle = Bot::new(Color::White, 100).start_search(board, History::new(&board, 0));
+        handle.stopper().stop();
+        let (_, result) = handle.wait();
+        // falls back to a legal move
+        assert!(MoveGen::new_legal(&board).any(|m| Some(m) == result.best_move));
+    }
+}
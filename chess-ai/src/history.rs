@@ -0,0 +1,152 @@
+// positions played so far in a game, needed to detect draws by repetition and by the fifty-move rule
+use chess::{Action, Board, ChessMove, Game, Piece};
+
+#[derive(Clone, Debug)]
+pub struct History {
+    // zobrist hashes of all positions of the game, the last one is the current position
+    hashes: Vec<u64>,
+    // halfmoves since the last capture or pawn move
+    halfmove_clock: u32,
+}
+
+impl History {
+    // history of a game starting at the given position
+    // the halfmove clock can be taken from the FEN of the starting position
+    pub fn new(start: &Board, halfmove_clock: u32) -> History {
+        History {
+            hashes: vec![start.get_hash()],
+            halfmove_clock,
+        }
+    }
+
+    // history of a game starting at the given position, followed by the given moves
+    pub fn from_moves(start: &Board, moves: &[ChessMove]) -> History {
+        History::new(start, 0).with_moves(start, moves)
+    }
+
+    // history of a chess::Game, the game doesn't expose its starting position and halfmove clock so they have to be passed as well
+    pub fn from_game(start: &Board, halfmove_clock: u32, game: &Game) -> History {
+        History::new(start, halfmove_clock).with_moves(start, &game_moves(game))
+    }
+
+    fn with_moves(mut self, start: &Board, moves: &[ChessMove]) -> History {
+        let mut board = *start;
+        for m in moves {
+            self.push(&board, *m);
+            board = board.make_move_new(*m);
+        }
+        self
+    }
+
+    // record a move, `board` is the position before the move
+    pub fn push(&mut self, board: &Board, m: ChessMove) {
+        self.halfmove_clock = if resets_halfmove_clock(board, m) {
+            0
+        } else {
+            self.halfmove_clock + 1
+        };
+        self.hashes.push(board.make_move_new(m).get_hash());
+    }
+
+    pub fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+}
+
+// the halfmove clock field of a FEN, 0 if it's missing
+pub fn fen_halfmove_clock(fen: &str) -> u32 {
+    fen.split_whitespace()
+        .nth(4)
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(0)
+}
+
+// moves played in a chess::Game, without draw offers and resignations
+pub fn game_moves(game: &Game) -> Vec<ChessMove> {
+    game.actions()
+        .iter()
+        .filter_map(|a| match a {
+            Action::MakeMove(m) => Some(*m),
+            _ => None,
+        })
+        .collect()
+}
+
+// captures and pawn moves are irreversible, positions before them can't be repeated
+pub fn resets_halfmove_clock(board: &Board, m: ChessMove) -> bool {
+    board.piece_on(m.get_source()) == Some(Piece::Pawn) || board.piece_on(m.get_dest()).is_some()
+}
+
+// whether the position at the end of `hashes` already occurred before, looking back at most `halfmove_clock` halfmoves
+pub fn is_repetition(hashes: &[u64], halfmove_clock: u32) -> bool {
+    match hashes.last() {
+        Some(current) => earlier_positions(hashes, halfmove_clock).any(|h| h == current),
+        None => false,
+    }
+}
+
+// how often the position at the end of `hashes` occurred before, looking back at most `halfmove_clock` halfmoves
+pub fn repetitions(hashes: &[u64], halfmove_clock: u32) -> usize {
+    match hashes.last() {
+        Some(current) => earlier_positions(hashes, halfmove_clock)
+            .filter(|h| *h == current)
+            .count(),
+        None => 0,
+    }
+}
+
+// only positions with the same side to move can be equal, so go back two halfmoves at a time
+fn earlier_positions(hashes: &[u64], halfmove_clock: u32) -> impl Iterator<Item = &u64> {
+    hashes
+        .iter()
+        .rev()
+        .take(halfmove_clock as usize + 1)
+        .skip(2)
+        .step_by(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::Square;
+
+    #[test]
+    fn repetition_and_clock() {
+        let start = Board::default();
+        let shuffle = [
+            ChessMove::new(Square::G1, Square::F3, None),
+            ChessMove::new(Square::G8, Square::F6, None),
+            ChessMove::new(Square::F3, Square::G1, None),
+            ChessMove::new(Square::F6, Square::G8, None),
+        ];
+        let history = History::from_moves(&start, &shuffle[..3]);
+        assert!(!is_repetition(history.hashes(), history.halfmove_clock()));
+
+        let mut game = Game::new_with_board(start);
+        shuffle.iter().for_each(|m| {
+            game.make_move(*m);
+        });
+        let history = History::from_game(&start, 0, &game);
+        assert_eq!(history.halfmove_clock(), 4);
+        assert_eq!(History::from_game(&start, 90, &game).halfmove_clock(), 94);
+        assert!(is_repetition(history.hashes(), history.halfmove_clock()));
+        assert_eq!(repetitions(history.hashes(), history.halfmove_clock()), 1);
+
+        // a pawn move resets the clock
+        let mut history = history;
+        let board = shuffle.iter().fold(start, |b, m| b.make_move_new(*m));
+        history.push(&board, ChessMove::new(Square::E2, Square::E4, None));
+        assert_eq!(history.halfmove_clock(), 0);
+        assert!(!is_repetition(history.hashes(), history.halfmove_clock()));
+    }
+
+    #[test]
+    fn fen_clock() {
+        assert_eq!(fen_halfmove_clock("8/8/8/8/8/8/8/K6k w - - 37 80"), 37);
+        assert_eq!(fen_halfmove_clock("8/8/8/8/8/8/8/K6k w - -"), 0);
+    }
+}
@@ -1,19 +1,52 @@
-use chess::{
-    self, BitBoard, Board, BoardStatus, ChessMove, Color, File, Game, MoveGen, Piece, Rank, Square,
-    EMPTY,
-};
+use chess::{self, Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square, EMPTY};
 use std::cmp;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
-    }
-}
+pub mod arena;
+pub mod bench;
+pub mod clock;
+pub mod engine;
+pub mod external;
+pub mod handle;
+pub mod history;
+pub mod movepick;
+pub mod outcome;
+pub mod pgn;
+pub mod player;
+pub mod record;
+pub mod result;
+pub mod san;
+pub mod see;
+pub mod stats;
+pub mod tt;
+pub mod uci;
+pub mod xboard;
+
+pub use handle::{SearchHandle, SearchStopper};
+pub use history::History;
+pub use result::{Score, SearchResult};
+
+use history::{is_repetition, resets_halfmove_clock};
+use movepick::{is_quiet, Heuristics, MovePicker, MAX_PLY};
+use see::{see, threat_on};
+use tt::{Bound, TranspositionTable, DEFAULT_HASH_SIZE_MB};
 
 const INFINITY: i32 = i32::MAX - 2;
+// initial half width of the aspiration window, doubled every time the score falls outside of it
+const ASPIRATION_WINDOW: i32 = 50;
+// beyond this width the window is opened completely
+const ASPIRATION_MAX_WINDOW: i32 = 800;
+// the scores of the first iterations are too unstable for aspiration windows
+const ASPIRATION_MIN_DEPTH: u8 = 4;
+// positional gain a capture in the quiescence search is assumed to make at most, on top of the captured material
+const DELTA_MARGIN: i32 = 200;
+
+// piece values used for move ordering, indexed by Piece::to_index()
+pub const PIECE_VALUES: [i32; 6] = [100, 300, 300, 500, 900, 20000];
 
 const _MG_PAWN_TABLE: [i32; 64] = [
     0, 0, 0, 0, 0, 0, 0, 0, 98, 134, 61, 95, 68, 126, 34, -11, -6, 7, 26, 31, 65, 56, 25, -20, -14,
@@ -98,185 +131,729 @@ pub const PST: [[[i32; 64]; 2]; 6] = [
     [_MG_KING_TABLE, _EG_KING_TABLE],
 ];
 
+// receives the result of every completed iteration of a search
+pub type SearchLog = Box<dyn FnMut(&SearchResult) + Send>;
+
 pub struct Bot {
     pub color: Color,
-    objective: i32,
     depth: u8,
-    _debug: bool,
+    time_limit: Option<Duration>,
+    node_limit: Option<u64>,
+    log: Option<SearchLog>,
+    // selective search, can be turned off for comparisons
+    null_move_pruning: bool,
+    late_move_reductions: bool,
+    principal_variation_search: bool,
+    aspiration_windows: bool,
+    // score of a draw for the side the bot searches for, in centipawns; positive values make the bot avoid draws
+    contempt: i32,
+    // number of threads searching in parallel
+    threads: usize,
+    // shared with the helper threads
+    tt: Arc<TranspositionTable>,
+    // tells all threads to stop searching
+    abort: Arc<AtomicBool>,
+    // result of the last completed iteration, read by a SearchHandle while the search is running
+    progress: Option<Arc<Mutex<Option<SearchResult>>>>,
+    heuristics: Heuristics,
+    // principal variation of the last completed search
+    pv: Vec<ChessMove>,
+    // state of the currently running search
+    // triangular pv table: pv_table[ply] holds the best line found so far from the node at this ply
+    pv_table: Vec<Vec<ChessMove>>,
+    deadline: Option<Instant>,
+    nodes: u64,
+    qnodes: u64,
+    stopped: bool,
+    // player_obj of the side to move at the root
+    root_player: i32,
+    // hashes of the game positions followed by the positions on the current search path
+    positions: Vec<u64>,
+    // halfmove clocks of the positions on the current search path, starting with the root
+    halfmove_clocks: Vec<u32>,
+    // set while searching the reply to a null move, two null moves in a row are not allowed
+    after_null_move: bool,
 }
 
 impl Bot {
-    pub fn new(color: Color, depth: u8, _debug: bool) -> Bot {
+    pub fn new(color: Color, depth: u8) -> Bot {
+        Bot::with_tt(
+            color,
+            depth,
+            Arc::new(TranspositionTable::new(DEFAULT_HASH_SIZE_MB)),
+        )
+    }
+
+    fn with_tt(color: Color, depth: u8, tt: Arc<TranspositionTable>) -> Bot {
         Bot {
             color,
-            objective: if color == Color::White { 1 } else { -1 },
             depth,
-            _debug,
+            time_limit: None,
+            node_limit: None,
+            log: None,
+            null_move_pruning: true,
+            late_move_reductions: true,
+            principal_variation_search: true,
+            aspiration_windows: true,
+            contempt: 0,
+            threads: 1,
+            tt,
+            abort: Arc::new(AtomicBool::new(false)),
+            progress: None,
+            heuristics: Heuristics::new(),
+            pv: Vec::new(),
+            pv_table: vec![Vec::new(); MAX_PLY + 1],
+            deadline: None,
+            nodes: 0,
+            qnodes: 0,
+            stopped: false,
+            root_player: 1,
+            positions: Vec::new(),
+            halfmove_clocks: Vec::new(),
+            after_null_move: false,
         }
     }
 
+    // maximum search depth
+    pub fn set_depth(&mut self, depth: u8) {
+        self.depth = depth;
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    // maximum wall-clock time spent on a single move, None means no limit
+    pub fn set_time_limit(&mut self, time_limit: Option<Duration>) {
+        self.time_limit = time_limit;
+    }
+
+    // maximum number of nodes searched for a single move, None means no limit
+    pub fn set_node_limit(&mut self, node_limit: Option<u64>) {
+        self.node_limit = node_limit;
+    }
+
+    // resize the transposition table, this also clears it
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(size_mb));
+    }
+
+    // search with multiple threads (lazy SMP): helper threads search the same position and share the
+    // transposition table, so the main thread finds more results there. with one thread the search is deterministic
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    // report the progress of every search, None disables logging
+    pub fn set_log(&mut self, log: Option<SearchLog>) {
+        self.log = log;
+    }
+
+    // skip the search of positions where passing the move would still be good enough
+    pub fn set_null_move_pruning(&mut self, enabled: bool) {
+        self.null_move_pruning = enabled;
+    }
+
+    // search quiet moves sorted late in the move list with reduced depth
+    pub fn set_late_move_reductions(&mut self, enabled: bool) {
+        self.late_move_reductions = enabled;
+    }
+
+    // search all moves but the first with a zero window
+    pub fn set_principal_variation_search(&mut self, enabled: bool) {
+        self.principal_variation_search = enabled;
+    }
+
+    // search the root with a narrow window around the score of the previous iteration
+    pub fn set_aspiration_windows(&mut self, enabled: bool) {
+        self.aspiration_windows = enabled;
+    }
+
+    // how much the bot dislikes draws by repetition or the fifty-move rule, in centipawns
+    // negative values make it seek draws
+    pub fn set_contempt(&mut self, contempt: i32) {
+        self.contempt = contempt;
+    }
+
+    // forget everything learned in the previous game
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.heuristics.clear();
+    }
+
     pub fn eval(&self, board: &Board) -> i32 {
         evaluate(board)
     }
 
-    pub fn get_move(&self, board: Board) -> ChessMove {
+    // the line the bot expects to be played, starting with the move returned by the last search
+    pub fn principal_variation(&self) -> &[ChessMove] {
+        &self.pv
+    }
+
+    // search a position without knowing the moves that led to it
+    pub fn get_move(&mut self, board: Board) -> SearchResult {
+        self.get_move_with_history(board, &History::new(&board, 0))
+    }
+
+    // search the current position of a game, the history is used to recognize draws by repetition and the fifty-move rule
+    // the last position of the history has to be the board
+    pub fn get_move_with_history(&mut self, board: Board, history: &History) -> SearchResult {
+        self.abort.store(false, Ordering::Relaxed);
+        self.run(board, history)
+    }
+
+    // search with all threads, stops early when the abort flag is set
+    fn run(&mut self, board: Board, history: &History) -> SearchResult {
+        if self.threads == 1 {
+            return self.search(board, history, 1);
+        }
+
+        let mut helpers: Vec<Bot> = (1..self.threads).map(|_| self.helper()).collect();
+        let (mut result, helper_results) = thread::scope(|s| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .enumerate()
+                .map(|(i, helper)| {
+                    // every other helper starts one iteration ahead, so the threads search different depths
+                    let first_depth = 1 + (i as u8 + 1) % 2;
+                    s.spawn(move || helper.search(board, history, first_depth))
+                })
+                .collect();
+            let result = self.search(board, history, 1);
+            // the helpers only search until the main thread is done
+            self.abort.store(true, Ordering::Relaxed);
+            let helper_results: Vec<SearchResult> = handles
+                .into_iter()
+                .map(|h| h.join().expect("Search thread panicked"))
+                .collect();
+            (result, helper_results)
+        });
+        for r in helper_results {
+            result.nodes += r.nodes;
+            result.qnodes += r.qnodes;
+        }
+        result
+    }
+
+    // a bot for a helper thread, with the same configuration and transposition table but without limits
+    fn helper(&self) -> Bot {
+        let mut helper = Bot::with_tt(self.color, self.depth, Arc::clone(&self.tt));
+        helper.abort = Arc::clone(&self.abort);
+        helper.null_move_pruning = self.null_move_pruning;
+        helper.late_move_reductions = self.late_move_reductions;
+        helper.principal_variation_search = self.principal_variation_search;
+        helper.aspiration_windows = self.aspiration_windows;
+        helper.contempt = self.contempt;
+        helper
+    }
+
+    // iterative deepening search of a single thread, starting at the given depth
+    fn search(&mut self, board: Board, history: &History, first_depth: u8) -> SearchResult {
         // features:
         // negamax + alpha beta
-        // TODO:
-        // iterative deepening
-        // transposition tables
-
-        println!("Searching for move...");
-        let (pos_score, best_move, positions) =
-            self.negamax(&board, self.depth, -INFINITY, INFINITY, self.objective);
-
-        // some output
-        println!();
-        println!(
-            "Score for current position (white's perspective): {}",
-            self.objective * pos_score
-        );
-        if let Some(m) = best_move {
-            println!("Move chosen: {:?}", m);
-            m
+        // iterative deepening with time & node limits
+        // transposition table
+        // move ordering: hash move, MVV-LVA, killer moves, history heuristic
+        // principal variation
+        // draws by repetition and fifty-move rule, with contempt
+        // null move pruning, late move reductions
+        // principal variation search, aspiration windows
+        // lazy SMP
+
+        let start = Instant::now();
+        self.deadline = self.time_limit.map(|t| start + t);
+        self.nodes = 0;
+        self.qnodes = 0;
+        self.stopped = false;
+        self.after_null_move = false;
+        self.heuristics.new_search();
+        let player_obj = if board.side_to_move() == Color::White {
+            1
         } else {
-            println!("No move possible!");
-            ChessMove::new(Square::A1, Square::A2, None)
+            -1
+        };
+        debug_assert_eq!(history.hashes().last(), Some(&board.get_hash()));
+        self.root_player = player_obj;
+        self.positions = history.hashes().to_vec();
+        self.halfmove_clocks = vec![history.halfmove_clock()];
+
+        // search depth 1, 2, 3, ... until the maximum depth is reached or the search runs out of time/nodes.
+        // only the result of the last completed iteration is used
+        let mut pos_score = 0;
+        let mut best_move = None;
+        let mut depth_reached = 0;
+        self.pv.clear();
+        for depth in first_depth..=self.depth {
+            let (score, m) = self.search_root(&board, depth, pos_score, player_obj);
+            if self.stopped {
+                // an aborted iteration is only used if no iteration has been completed yet
+                if best_move.is_none() && m.is_some() {
+                    pos_score = score;
+                    best_move = m;
+                    self.pv = self.pv_table[0].clone();
+                }
+                break;
+            }
+            pos_score = score;
+            best_move = m;
+            depth_reached = depth;
+            self.pv = self.pv_table[0].clone();
+            // the line is cut short where transposition table hits ended the search, continue it with the stored moves
+            extend_pv_from_tt(&self.tt, &board, &mut self.pv, depth as usize);
+
+            if self.log.is_some() || self.progress.is_some() {
+                let result = self.result(best_move, pos_score, depth_reached, start);
+                if let Some(log) = self.log.as_mut() {
+                    log(&result);
+                }
+                if let Some(progress) = &self.progress {
+                    *progress.lock().expect("Progress lock poisoned") = Some(result);
+                }
+            }
+
+            // a mate has been found within the search depth (not just in the quiescence search),
+            // searching deeper won't change the result
+            if let Score::Mate(n) = to_score(pos_score) {
+                if 2 * n.abs() - 1 <= depth as i32 {
+                    break;
+                }
+            }
+        }
+
+        // fall back to the first legal move if the search was stopped before finding any move
+        if best_move.is_none() {
+            best_move = MoveGen::new_legal(&board).next();
+            self.pv = best_move.into_iter().collect();
         }
+        self.result(best_move, pos_score, depth_reached, start)
     }
 
-    
+    // search the root with an aspiration window around the score of the previous iteration.
+    // the score rarely changes much between iterations and a narrow window cuts off more branches.
+    // if the score falls outside the window the search is repeated with a wider window on that side.
+    fn search_root(
+        &mut self,
+        board: &Board,
+        depth: u8,
+        previous_score: i32,
+        player_obj: i32,
+    ) -> (i32, Option<ChessMove>) {
+        let mut window = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = (-INFINITY, INFINITY);
+        let is_mate = matches!(to_score(previous_score), Score::Mate(_));
+        if self.aspiration_windows && depth >= ASPIRATION_MIN_DEPTH && !is_mate {
+            alpha = previous_score - window;
+            beta = previous_score + window;
+        }
+        loop {
+            let (score, m, _) = self.negamax(board, depth, 0, alpha, beta, player_obj);
+            if self.stopped {
+                return (score, m);
+            }
+            if score <= alpha && alpha > -INFINITY {
+                window *= 2;
+                alpha = if window > ASPIRATION_MAX_WINDOW {
+                    -INFINITY
+                } else {
+                    cmp::max(score - window, -INFINITY)
+                };
+            } else if score >= beta && beta < INFINITY {
+                window *= 2;
+                beta = if window > ASPIRATION_MAX_WINDOW {
+                    INFINITY
+                } else {
+                    cmp::min(score + window, INFINITY)
+                };
+            } else {
+                return (score, m);
+            }
+        }
+    }
 
-    pub fn negamax(
+    fn result(
         &self,
+        best_move: Option<ChessMove>,
+        score: i32,
+        depth: u8,
+        start: Instant,
+    ) -> SearchResult {
+        SearchResult {
+            best_move,
+            score: to_score(score),
+            depth,
+            nodes: self.nodes,
+            qnodes: self.qnodes,
+            time: start.elapsed(),
+            pv: self.pv.clone(),
+        }
+    }
+
+    // check whether the search has to be stopped because of the time or node limit
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if self.abort.load(Ordering::Relaxed) {
+            self.stopped = true;
+            return true;
+        }
+        let nodes = self.nodes + self.qnodes;
+        if let Some(limit) = self.node_limit {
+            if nodes >= limit {
+                self.stopped = true;
+            }
+        }
+        // checking the clock is comparatively expensive, only do it every 1024 nodes
+        if let Some(deadline) = self.deadline {
+            if nodes & 1023 == 0 && Instant::now() >= deadline {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+
+    pub fn negamax(
+        &mut self,
         board: &Board,
         depth: u8,
+        ply: u8,
         alpha: i32,
         beta: i32,
         player_obj: i32,
     ) -> (i32, Option<ChessMove>, u32) {
-        if depth == 0 || board.status() != BoardStatus::Ongoing {
-            // instead of returning score, start quiscence search (same search function, but only look at capture moves and keep going until no captures are left)
-            return (player_obj * self.eval(&board), None, 1);
+        self.pv_table[ply as usize].clear();
+        if depth == 0 {
+            // instead of returning the static evaluation, keep searching captures until the position is quiet
+            return (self.quiescence(board, alpha, beta, player_obj), None, 1);
+        }
+        self.nodes += 1;
+        if self.should_stop() {
+            return (0, None, 0);
+        }
+        if board.status() != BoardStatus::Ongoing {
+            return (player_obj * self.eval(board), None, 1);
+        }
+        // the game can't be drawn by the root move itself, the bot still has to return a move
+        if ply > 0 && self.is_draw() {
+            return (self.draw_score(player_obj), None, 1);
         }
         let mut alpha = alpha;
+        let mut beta = beta;
+        let alpha_orig = alpha;
+
+        // look up the position in the transposition table
+        // results of a search with at least the same depth can be used directly, except at the root where we need a move
+        let hash = board.get_hash();
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.probe(hash) {
+            // guard against hash collisions
+            hash_move = entry.best_move.filter(|m| board.legal(*m));
+            if ply > 0 && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return (entry.score, hash_move, 1),
+                    Bound::Lower => alpha = cmp::max(alpha, entry.score),
+                    Bound::Upper => beta = cmp::min(beta, entry.score),
+                }
+                if alpha >= beta {
+                    return (entry.score, hash_move, 1);
+                }
+            }
+        }
+
+        let in_check = *board.checkers() != EMPTY;
+        let after_null_move = std::mem::replace(&mut self.after_null_move, false);
+
+        // null move pruning: let the opponent move twice in a row. if a reduced search still fails high,
+        // the position is so good that a full search would fail high as well.
+        // in zugzwang positions passing would be the best move, so it isn't tried when only the king and pawns are left
+        if self.null_move_pruning
+            && ply > 0
+            && depth >= 3
+            && !in_check
+            && !after_null_move
+            && beta < INFINITY - MAX_PLY as i32
+            && has_non_pawn_material(board, board.side_to_move())
+        {
+            if let Some(null_board) = board.null_move() {
+                let r = if depth >= 6 { 3 } else { 2 };
+                self.after_null_move = true;
+                // positions before the null move can't be repeated after it
+                let (score, _) = self.search_child(
+                    &null_board,
+                    0,
+                    depth - 1 - r,
+                    ply,
+                    -beta,
+                    -beta + 1,
+                    player_obj,
+                );
+                self.after_null_move = false;
+                if self.stopped {
+                    return (0, None, 0);
+                }
+                if score >= beta {
+                    return (beta, None, 1);
+                }
+            }
+        }
 
-        let mut child_nodes = MoveGen::new_legal(&board);
         let mut best_score = i32::MIN;
         let mut best_move = None;
 
         let mut count = 0;
 
-        // TODO: better move ordering
-        // use movegen.filter... ?
-        // create a sorted vector?
-
-        // first, only iterate capture moves
-        let targets = board.color_combined(!board.side_to_move());
-        child_nodes.set_iterator_mask(*targets);
-
-        for m in &mut child_nodes {
-            let (child_score, child_move, c) = self.negamax(
-                &board.make_move_new(m),
-                depth - 1,
-                -beta,
-                -alpha,
-                -player_obj,
-            );
-            count += c;
-            // if a move leads to checkmate, prefer the shortest sequence
-            let child_score = if child_score >= INFINITY - 1 - self.depth as i32 {
-                -(child_score - 1)
+        let moves = MovePicker::new(board, hash_move, &self.heuristics, ply);
+        for (moves_searched, m) in moves.enumerate() {
+            let child = board.make_move_new(m);
+            let clock = if resets_halfmove_clock(board, m) {
+                0
             } else {
-                -child_score
+                self.halfmove_clocks[ply as usize] + 1
+            };
+
+            // late move reductions: quiet moves sorted late are unlikely to be good, search them with reduced depth first.
+            // only if the reduced search beats alpha the move gets searched again with the full depth
+            let reduction = if self.late_move_reductions
+                && depth >= 3
+                && moves_searched >= 3
+                && !in_check
+                && is_quiet(board, m)
+                && *child.checkers() == EMPTY
+            {
+                if moves_searched >= 6 {
+                    2
+                } else {
+                    1
+                }
+            } else {
+                0
             };
+            let mut result = None;
+            if reduction > 0 {
+                let (score, c) = self.search_child(
+                    &child,
+                    clock,
+                    depth - 1 - reduction,
+                    ply,
+                    -alpha - 1,
+                    -alpha,
+                    player_obj,
+                );
+                if score <= alpha {
+                    result = Some((score, c));
+                }
+            }
+            // principal variation search: the first move is expected to be the best one.
+            // the other moves are searched with a zero window, which only proves that they are worse than alpha.
+            // if that fails, the move is searched again with the full window to get its exact score
+            if result.is_none() && self.principal_variation_search && moves_searched > 0 {
+                let (score, c) = self.search_child(
+                    &child,
+                    clock,
+                    depth - 1,
+                    ply,
+                    -alpha - 1,
+                    -alpha,
+                    player_obj,
+                );
+                if score <= alpha || score >= beta {
+                    result = Some((score, c));
+                }
+            }
+            let (child_score, c) = match result {
+                Some(r) => r,
+                None => self.search_child(&child, clock, depth - 1, ply, -beta, -alpha, player_obj),
+            };
+            if self.stopped {
+                return (best_score, best_move, count);
+            }
+            count += c;
             if child_score > best_score {
                 best_score = child_score;
                 best_move = Some(m);
             }
+            if child_score > alpha {
+                // new best line: this move followed by the best line of the child
+                let (parent, child) = self.pv_table.split_at_mut(ply as usize + 1);
+                let line = &mut parent[ply as usize];
+                line.clear();
+                line.push(m);
+                line.extend_from_slice(&child[0]);
+            }
 
             alpha = cmp::max(alpha, child_score);
             if alpha >= beta {
+                // remember quiet moves that caused a cutoff, they are likely good in sibling positions too
+                if is_quiet(board, m) {
+                    self.heuristics.update(board, m, depth, ply);
+                }
                 break;
             }
         }
 
-        // all the other moves
-        child_nodes.set_iterator_mask(!EMPTY);
-        for m in &mut child_nodes {
-            let (child_score, child_move, c) = self.negamax(
-                &board.make_move_new(m),
-                depth - 1,
-                -beta,
-                -alpha,
-                -player_obj,
-            );
-            count += c;
+        let bound = if best_score <= alpha_orig {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.tt.store(hash, depth, bound, best_score, best_move);
+
+        (best_score, best_move, count)
+    }
+
+    // search a child position of the node at `ply`, the score is returned from the perspective of the parent
+    #[allow(clippy::too_many_arguments)]
+    fn search_child(
+        &mut self,
+        child: &Board,
+        halfmove_clock: u32,
+        depth: u8,
+        ply: u8,
+        alpha: i32,
+        beta: i32,
+        player_obj: i32,
+    ) -> (i32, u32) {
+        self.positions.push(child.get_hash());
+        self.halfmove_clocks.push(halfmove_clock);
+        let (child_score, _child_move, c) =
+            self.negamax(child, depth, ply + 1, alpha, beta, -player_obj);
+        self.positions.pop();
+        self.halfmove_clocks.pop();
+        // the score of an aborted search is meaningless
+        if self.stopped {
+            return (0, c);
+        }
+        // if a move leads to checkmate, prefer the shortest sequence
+        let score = if child_score >= INFINITY - 1 - self.depth as i32 {
+            -(child_score - 1)
+        } else {
+            -child_score
+        };
+        (score, c)
+    }
+
+    // whether the position at the end of the current search path is a draw by repetition or the fifty-move rule
+    // a single repetition is enough, if it was good to repeat once it is also good to repeat again
+    fn is_draw(&self) -> bool {
+        let clock = *self.halfmove_clocks.last().unwrap_or(&0);
+        clock >= 100 || is_repetition(&self.positions, clock)
+    }
+
+    // a draw is worth -contempt for the side the bot searches for
+    fn draw_score(&self, player_obj: i32) -> i32 {
+        if player_obj == self.root_player {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    // quiescence search: only look at captures and promotions (or all moves when in check) until the position is quiet,
+    // so that the evaluation isn't done in the middle of an exchange
+    fn quiescence(&mut self, board: &Board, alpha: i32, beta: i32, player_obj: i32) -> i32 {
+        self.qnodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+        if board.status() != BoardStatus::Ongoing {
+            return player_obj * self.eval(board);
+        }
+        let mut alpha = alpha;
+        let in_check = *board.checkers() != EMPTY;
+
+        let mut best_score = -INFINITY;
+        let mut stand_pat = -INFINITY;
+        // when in check, all moves have to be considered to get out of check
+        if !in_check {
+            // stand pat: the side to move can usually do at least as well as the static evaluation by playing a quiet move
+            stand_pat = player_obj * self.eval(board);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = cmp::max(alpha, stand_pat);
+            best_score = stand_pat;
+        }
+
+        for m in MovePicker::new_quiescence(board) {
+            // captures that lose material according to the static exchange evaluation are not worth searching
+            if !in_check && see(board, m) < 0 {
+                continue;
+            }
+            // delta pruning: skip captures that can't raise the score to alpha even with a safety margin
+            if !in_check && m.get_promotion().is_none() {
+                let victim = board.piece_on(m.get_dest()).unwrap_or(Piece::Pawn);
+                if stand_pat + PIECE_VALUES[victim.to_index()] + DELTA_MARGIN < alpha {
+                    continue;
+                }
+            }
+            let child_score = self.quiescence(&board.make_move_new(m), -beta, -alpha, -player_obj);
+            if self.stopped {
+                return best_score;
+            }
             // if a move leads to checkmate, prefer the shortest sequence
             let child_score = if child_score >= INFINITY - 1 - self.depth as i32 {
                 -(child_score - 1)
             } else {
                 -child_score
             };
-            if child_score > best_score {
-                best_score = child_score;
-                best_move = Some(m);
-            }
-
+            best_score = cmp::max(best_score, child_score);
             alpha = cmp::max(alpha, child_score);
             if alpha >= beta {
                 break;
             }
         }
-        (best_score, best_move, count)
+        best_score
     }
 }
 
-fn negamax_no_moveorder(
-    &self,
-    board: Board,
-    depth: u8,
-    alpha: i32,
-    beta: i32,
-    player_obj: i32,
-) -> (i32, Option<ChessMove>, u32) {
-    if depth == 0 || board.status() != BoardStatus::Ongoing {
-        // instead of returning score, start quiscence search (same search function, but only look at capture moves and keep going until no captures are left)
-        return (player_obj * self.eval(&board), None, 1);
-    }
-    let mut alpha = alpha;
-
-    let mut child_nodes = MoveGen::new_legal(&board);
-    let mut score = i32::MIN;
-    let mut best_move = None;
-    let mut count = 0;
-
-    for m in &mut child_nodes {
-        let (child_score, child_move, c) = self.negamax_no_moveorder(
-            board.make_move_new(m),
-            depth - 1,
-            -beta,
-            -alpha,
-            -player_obj,
-        );
-        count += c;
-        // if a move leads to checkmate, prefer the shortest sequence
-        let child_score = if child_score >= INFINITY - 1 - self.depth as i32 {
-            -(child_score - 1)
-        } else {
-            -child_score
-        };
-        if child_score > score {
-            score = child_score;
-            best_move = Some(m);
-        }
-        alpha = cmp::max(alpha, child_score);
-        if alpha >= beta {
-            break;
+// whether a side has any pieces besides king and pawns
+fn has_non_pawn_material(board: &Board, color: Color) -> bool {
+    let pawns_and_king = board.pieces(Piece::Pawn) | board.pieces(Piece::King);
+    *board.color_combined(color) & !pawns_and_king != EMPTY
+}
+
+// follow the best moves stored in the transposition table from the end of a line, up to a maximum length
+fn extend_pv_from_tt(
+    tt: &TranspositionTable,
+    root: &Board,
+    pv: &mut Vec<ChessMove>,
+    max_len: usize,
+) {
+    let mut board = *root;
+    for m in pv.iter() {
+        board = board.make_move_new(*m);
+    }
+    while pv.len() < max_len {
+        match tt.probe(board.get_hash()).and_then(|e| e.best_move) {
+            Some(m) if board.legal(m) => {
+                pv.push(m);
+                board = board.make_move_new(m);
+            }
+            _ => break,
         }
     }
+}
+
+// convert an internal search score to centipawns or moves to mate
+// the winning side's mate score decreases by one for each of its moves before the mate,
+// the losing side's mate score increases by one for each of its moves before getting mated
+fn to_score(score: i32) -> Score {
+    if score >= INFINITY - MAX_PLY as i32 {
+        Score::Mate(INFINITY - score + 1)
+    } else if score <= -INFINITY + MAX_PLY as i32 {
+        Score::Mate(-(INFINITY + score))
+    } else {
+        Score::Centipawns(score)
+    }
+}
 
-    (score, best_move, count)
+// whether a move captures a piece, including en passant
+pub fn is_capture(board: &Board, m: ChessMove) -> bool {
+    board.piece_on(m.get_dest()).is_some()
+        || (board.piece_on(m.get_source()) == Some(Piece::Pawn)
+            && m.get_source().get_file() != m.get_dest().get_file())
 }
 
 fn force_king_to_corner(king_w_idx: i32, king_b_idx: i32) -> (i32, i32) {
@@ -290,12 +867,11 @@ fn force_king_to_corner(king_w_idx: i32, king_b_idx: i32) -> (i32, i32) {
     let center_distance_w_x = cmp::max(3 - king_w_x, king_w_x - 4);
     let center_distance_w_y = cmp::max(3 - king_w_y, king_w_y - 4);
     let center_distance_w = center_distance_w_x + center_distance_w_y;
-    let kings_distance = (king_w_x - king_b_x).abs() + (king_w_y - king_b_y).abs();
+    let _kings_distance = (king_w_x - king_b_x).abs() + (king_w_y - king_b_y).abs();
     (center_distance_b, center_distance_w)
 }
 
 fn evaluate(board: &Board) -> i32 {
-    
     if board.status() == BoardStatus::Stalemate {
         return 0;
     } else if board.status() == BoardStatus::Checkmate {
@@ -306,7 +882,6 @@ fn evaluate(board: &Board) -> i32 {
         }
     }
 
-
     // different evaluation based on board state
     // specifically endgame or heuristics when no pieces can be captures (bring own pieces closer to enemy king)
     // sebastian lague ForceKingToCornerEndgameEval
@@ -384,7 +959,7 @@ fn evaluate(board: &Board) -> i32 {
     // might be either if:
     // Both sides have no queens or
     // Every side which has a queen has additionally no other pieces or one minorpiece maximum.
-    let is_endgame = if (queens_w == 0 || queens_w + rooks_w + bishops_w + knights_w <= 2)
+    let _is_endgame = if (queens_w == 0 || queens_w + rooks_w + bishops_w + knights_w <= 2)
         && (queens_b == 0 || queens_b + rooks_b + bishops_b + knights_b <= 2)
     {
         1
@@ -468,20 +1043,35 @@ fn evaluate(board: &Board) -> i32 {
     // possible moves
     let movegen = MoveGen::new_legal(board);
 
-    let num_moves_current_player = movegen.len();
+    let _num_moves_current_player = movegen.len();
     let mobility = 0;
 
-    mat_score + mobility + endgame_force_king
+    // hanging pieces: the side to move can usually only save one of its attacked pieces,
+    // so the opponent is likely to win the second most valuable one
+    let side_to_move = board.side_to_move();
+    let mut threats: Vec<i32> = (*board.color_combined(side_to_move) & !board.pieces(Piece::King))
+        .map(|sq| threat_on(board, sq))
+        .filter(|t| *t > 0)
+        .collect();
+    threats.sort_unstable_by(|a, b| b.cmp(a));
+    let hanging = threats.get(1).copied().unwrap_or(0);
+    let hanging = if side_to_move == Color::White {
+        -hanging
+    } else {
+        hanging
+    };
+
+    mat_score + mobility + endgame_force_king + hanging
 }
 
-fn eval_from_fen(fen: String) -> i32 {
-    let b = Board::from_fen(fen).expect("Valid FEN");
+pub fn eval_from_fen(fen: String) -> i32 {
+    let b = Board::from_str(&fen).expect("Valid FEN");
     let e = evaluate(&b);
     println!("{}", e);
     e
 }
 
-fn eval_piecescore_simple(board: &Board) -> i32 {
+pub fn eval_piecescore_simple(board: &Board) -> i32 {
     let pawn = 10;
     let bishop = 30;
     let knight = 30;
@@ -528,7 +1118,197 @@ fn eval_piecescore_simple(board: &Board) -> i32 {
     let queens = board.pieces(Piece::Queen);
     mat_white += (queens & white_pieces).popcnt() as i32 * queen;
     mat_black += (queens & black_pieces).popcnt() as i32 * queen;
-    let mat_score = mat_white - mat_black;
+    let _mat_score = mat_white - mat_black;
 
     mat_white - mat_black
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let result = 2 + 2;
+        assert_eq!(result, 4);
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").expect("Valid FEN");
+        let mut bot = Bot::new(Color::White, 3);
+        let result = bot.get_move(board);
+        let m = ChessMove::new(Square::A1, Square::A8, None);
+        assert_eq!(result.best_move, Some(m));
+        assert_eq!(result.score, Score::Mate(1));
+        // the transposition table is filled now, searching again must give the same move
+        assert_eq!(bot.get_move(board).best_move, Some(m));
+        bot.new_game();
+        assert_eq!(bot.get_move(board).best_move, Some(m));
+    }
+
+    #[test]
+    fn quiescence_sees_recapture() {
+        // Qxd5 wins a pawn at depth 1, but the queen gets recaptured
+        let board = Board::from_str("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").expect("Valid FEN");
+        let mut bot = Bot::new(Color::White, 1);
+        let result = bot.get_move(board);
+        assert_ne!(
+            result.best_move,
+            Some(ChessMove::new(Square::D1, Square::D5, None))
+        );
+        assert!(result.qnodes > 0);
+    }
+
+    #[test]
+    fn principal_variation() {
+        // back rank mate in two: Rd8+ Rxd8 Rxd8#
+        let board =
+            Board::from_str("2r3k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1").expect("Valid FEN");
+        let mut bot = Bot::new(Color::White, 4);
+        let result = bot.get_move(board);
+        assert_eq!(result.score, Score::Mate(2));
+        assert_eq!(result.pv, bot.principal_variation());
+        let pv = result.pv;
+        assert_eq!(pv.first(), result.best_move.as_ref());
+        assert!(pv.len() >= 3);
+        // the line must be playable from the root
+        let mut b = board;
+        for m in pv {
+            assert!(b.legal(m));
+            b = b.make_move_new(m);
+        }
+    }
+
+    #[test]
+    fn get_move_respects_time_limit() {
+        let mut bot = Bot::new(Color::White, 50);
+        bot.set_time_limit(Some(Duration::from_millis(200)));
+        let board = Board::default();
+        let start = Instant::now();
+        let result = bot.get_move(board);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(board.legal(result.best_move.unwrap()));
+    }
+
+    #[test]
+    fn get_move_respects_node_limit() {
+        let mut bot = Bot::new(Color::White, 50);
+        bot.set_node_limit(Some(5000));
+        let board = Board::default();
+        let result = bot.get_move(board);
+        assert!(result.nodes + result.qnodes <= 5000);
+        assert!(board.legal(result.best_move.unwrap()));
+    }
+
+    #[test]
+    fn no_legal_moves() {
+        // black is checkmated
+        let board = Board::from_str("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").expect("Valid FEN");
+        let mut bot = Bot::new(Color::Black, 3);
+        let result = bot.get_move(board);
+        assert_eq!(result.best_move, None);
+        assert!(result.pv.is_empty());
+    }
+
+    #[test]
+    fn log_receives_every_iteration() {
+        use std::sync::{Arc, Mutex};
+
+        let depths = Arc::new(Mutex::new(Vec::new()));
+        let log_depths = Arc::clone(&depths);
+        let mut bot = Bot::new(Color::White, 3);
+        bot.set_log(Some(Box::new(move |result: &SearchResult| {
+            log_depths.lock().unwrap().push(result.depth)
+        })));
+        bot.get_move(Board::default());
+        assert_eq!(*depths.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn search_features_can_be_disabled() {
+        let board =
+            Board::from_str("2r3k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1").expect("Valid FEN");
+        for enabled in [false, true] {
+            let mut bot = Bot::new(Color::White, 5);
+            bot.set_null_move_pruning(enabled);
+            bot.set_late_move_reductions(enabled);
+            bot.set_principal_variation_search(enabled);
+            bot.set_aspiration_windows(enabled);
+            assert_eq!(bot.get_move(board).score, Score::Mate(2));
+        }
+    }
+
+    #[test]
+    fn no_null_move_in_pawn_endgames() {
+        let board = Board::from_str("8/8/4k3/4p3/4P3/4K3/8/8 w - - 0 1").expect("Valid FEN");
+        assert!(!has_non_pawn_material(&board, Color::White));
+        let board = Board::from_str("8/8/4k3/4p3/4P3/4K3/8/7N w - - 0 1").expect("Valid FEN");
+        assert!(has_non_pawn_material(&board, Color::White));
+        assert!(!has_non_pawn_material(&board, Color::Black));
+    }
+
+    #[test]
+    fn single_thread_is_deterministic() {
+        let board =
+            Board::from_str("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8")
+                .expect("Valid FEN");
+        let mut bot = Bot::new(Color::White, 5);
+        let first = bot.get_move(board);
+        bot.new_game();
+        let second = bot.get_move(board);
+        assert_eq!(first.best_move, second.best_move);
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(first.pv, second.pv);
+    }
+
+    #[test]
+    fn multiple_threads() {
+        let board =
+            Board::from_str("2r3k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1").expect("Valid FEN");
+        let mut bot = Bot::new(Color::White, 6);
+        bot.set_threads(4);
+        let result = bot.get_move(board);
+        assert_eq!(result.score, Score::Mate(2));
+        assert!(board.legal(result.best_move.unwrap()));
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        // white is a queen up, but the next move without capture or pawn move draws the game
+        let board = Board::from_str("8/8/8/4k3/8/8/8/KQ6 w - - 99 60").expect("Valid FEN");
+        let mut bot = Bot::new(Color::White, 3);
+        let result = bot.get_move_with_history(board, &History::new(&board, 99));
+        assert_eq!(result.score, Score::Centipawns(0));
+        assert!(matches!(bot.get_move(board).score, Score::Centipawns(cp) if cp > 500));
+    }
+
+    #[test]
+    fn repetition_and_contempt() {
+        let start = Board::default();
+        let moves = [
+            ChessMove::new(Square::G1, Square::F3, None),
+            ChessMove::new(Square::G8, Square::F6, None),
+            ChessMove::new(Square::F3, Square::G1, None),
+            ChessMove::new(Square::F6, Square::G8, None),
+            ChessMove::new(Square::G1, Square::F3, None),
+            ChessMove::new(Square::G8, Square::F6, None),
+        ];
+        let board = moves.iter().fold(start, |b, m| b.make_move_new(*m));
+        let history = History::from_moves(&start, &moves);
+        let repeat = ChessMove::new(Square::F3, Square::G1, None);
+
+        // a bot that wants a draw repeats the position
+        let mut bot = Bot::new(Color::White, 3);
+        bot.set_contempt(-1000);
+        let result = bot.get_move_with_history(board, &history);
+        assert_eq!(result.best_move, Some(repeat));
+        assert_eq!(result.score, Score::Centipawns(1000));
+
+        // a bot that doesn't want a draw avoids it
+        let mut bot = Bot::new(Color::White, 3);
+        bot.set_contempt(1000);
+        let result = bot.get_move_with_history(board, &history);
+        assert_ne!(result.best_move, Some(repeat));
+    }
+}
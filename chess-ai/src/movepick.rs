@@ -0,0 +1,245 @@
+// move ordering
+// the better the moves are ordered, the more branches get cut off by alpha-beta
+use chess::{get_rank, BitBoard, Board, ChessMove, MoveGen, Piece, EMPTY};
+
+use crate::see::see;
+use crate::{is_capture, PIECE_VALUES};
+
+// maximum search depth for which killer moves are stored
+pub const MAX_PLY: usize = 256;
+
+// move classes, ordered from most to least promising
+const HASH_MOVE_SCORE: i32 = 1 << 30;
+const CAPTURE_SCORE: i32 = 1 << 28;
+const PROMOTION_SCORE: i32 = 1 << 27;
+const KILLER_SCORE: i32 = 1 << 26;
+// captures losing material are tried after all quiet moves
+const BAD_CAPTURE_SCORE: i32 = -(1 << 26);
+// history scores are kept below this value so quiet moves never get sorted before killer moves
+const HISTORY_MAX: i32 = 1 << 20;
+
+// quiet moves which caused a beta cutoff, remembered across the search
+pub struct Heuristics {
+    // two killer moves per ply
+    killers: Vec<[Option<ChessMove>; 2]>,
+    // history table indexed by [color][source][destination]
+    history: Box<[[[i32; 64]; 64]; 2]>,
+}
+
+impl Heuristics {
+    pub fn new() -> Heuristics {
+        Heuristics {
+            killers: vec![[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+        }
+    }
+
+    // called at the start of every search
+    // killer moves are specific to the position, history scores from the previous search are kept with less weight
+    pub fn new_search(&mut self) {
+        self.killers.iter_mut().for_each(|k| *k = [None; 2]);
+        self.age_history();
+    }
+
+    pub fn clear(&mut self) {
+        self.killers.iter_mut().for_each(|k| *k = [None; 2]);
+        *self.history = [[[0; 64]; 64]; 2];
+    }
+
+    fn age_history(&mut self) {
+        self.history
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|h| *h /= 2);
+    }
+
+    // a quiet move caused a beta cutoff
+    pub fn update(&mut self, board: &Board, m: ChessMove, depth: u8, ply: u8) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(m) {
+            killers[1] = killers[0];
+            killers[0] = Some(m);
+        }
+
+        let h = &mut self.history[board.side_to_move().to_index()][m.get_source().to_index()]
+            [m.get_dest().to_index()];
+        *h += depth as i32 * depth as i32;
+        if *h >= HISTORY_MAX {
+            self.age_history();
+        }
+    }
+
+    fn killer_rank(&self, m: ChessMove, ply: u8) -> Option<i32> {
+        self.killers[ply as usize]
+            .iter()
+            .position(|k| *k == Some(m))
+            .map(|i| i as i32)
+    }
+
+    fn history(&self, board: &Board, m: ChessMove) -> i32 {
+        self.history[board.side_to_move().to_index()][m.get_source().to_index()]
+            [m.get_dest().to_index()]
+    }
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Heuristics::new()
+    }
+}
+
+// most valuable victim, least valuable attacker
+pub fn mvv_lva(board: &Board, m: ChessMove) -> i32 {
+    // en passant is the only capture where no piece is on the destination square
+    let victim = board.piece_on(m.get_dest()).unwrap_or(Piece::Pawn);
+    let attacker = board.piece_on(m.get_source()).unwrap_or(Piece::Pawn);
+    PIECE_VALUES[victim.to_index()] * 10 - PIECE_VALUES[attacker.to_index()] / 10
+}
+
+// whether a move is neither a capture nor a promotion
+pub fn is_quiet(board: &Board, m: ChessMove) -> bool {
+    !is_capture(board, m) && m.get_promotion().is_none()
+}
+
+// yields the legal moves of a position, most promising first
+// the moves are scored up front, but only sorted lazily, since a cutoff often happens after the first few moves
+pub struct MovePicker {
+    moves: Vec<(ChessMove, i32)>,
+}
+
+impl MovePicker {
+    // all legal moves: hash move, captures (MVV-LVA), queen promotions, killer moves, quiet moves by history score,
+    // captures losing material
+    pub fn new(
+        board: &Board,
+        hash_move: Option<ChessMove>,
+        heuristics: &Heuristics,
+        ply: u8,
+    ) -> MovePicker {
+        let moves = MoveGen::new_legal(board)
+            .map(|m| {
+                let score = if Some(m) == hash_move {
+                    HASH_MOVE_SCORE
+                } else if is_capture(board, m) {
+                    capture_score(board, m)
+                } else if m.get_promotion() == Some(Piece::Queen) {
+                    PROMOTION_SCORE
+                } else if let Some(rank) = heuristics.killer_rank(m, ply) {
+                    KILLER_SCORE - rank
+                } else if m.get_promotion().is_some() {
+                    // underpromotions are almost never the best move
+                    -HISTORY_MAX
+                } else {
+                    heuristics.history(board, m)
+                };
+                (m, score)
+            })
+            .collect();
+        MovePicker { moves }
+    }
+
+    // moves for the quiescence search: captures and promotions, or all moves if the side to move is in check
+    pub fn new_quiescence(board: &Board) -> MovePicker {
+        let mut child_nodes = MoveGen::new_legal(board);
+        let in_check = *board.checkers() != EMPTY;
+        if !in_check {
+            let mut targets = *board.color_combined(!board.side_to_move())
+                | get_rank(board.side_to_move().to_their_backrank());
+            if let Some(ep_dest) = board
+                .en_passant()
+                .and_then(|sq| sq.forward(board.side_to_move()))
+            {
+                targets |= BitBoard::from_square(ep_dest);
+            }
+            child_nodes.set_iterator_mask(targets);
+        }
+        let moves = child_nodes
+            .filter(|m| in_check || !is_quiet(board, *m))
+            .map(|m| {
+                let score = if is_capture(board, m) {
+                    capture_score(board, m)
+                } else if m.get_promotion().is_some() {
+                    PROMOTION_SCORE + promotion_value(m)
+                } else {
+                    0
+                };
+                (m, score)
+            })
+            .collect();
+        MovePicker { moves }
+    }
+}
+
+// good and equal captures by MVV-LVA, losing captures at the end
+fn capture_score(board: &Board, m: ChessMove) -> i32 {
+    let victim = board.piece_on(m.get_dest()).unwrap_or(Piece::Pawn);
+    let attacker = board.piece_on(m.get_source()).unwrap_or(Piece::Pawn);
+    // capturing a piece of at least the same value can't lose material, no need for the exchange evaluation
+    if PIECE_VALUES[attacker.to_index()] > PIECE_VALUES[victim.to_index()] && see(board, m) < 0 {
+        BAD_CAPTURE_SCORE + mvv_lva(board, m)
+    } else {
+        CAPTURE_SCORE + mvv_lva(board, m) + promotion_value(m)
+    }
+}
+
+fn promotion_value(m: ChessMove) -> i32 {
+    m.get_promotion().map_or(0, |p| PIECE_VALUES[p.to_index()])
+}
+
+impl Iterator for MovePicker {
+    type Item = ChessMove;
+
+    fn next(&mut self) -> Option<ChessMove> {
+        // selection sort step: swap the best remaining move to the end and pop it
+        let best = self
+            .moves
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (_, score))| *score)
+            .map(|(i, _)| i)?;
+        Some(self.moves.swap_remove(best).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::Square;
+    use std::str::FromStr;
+
+    #[test]
+    fn move_order() {
+        // white can capture the queen with the pawn, or a pawn defended by the queen with the rook
+        let board = Board::from_str("4k3/8/2q5/1P6/8/8/2p5/2R1K3 w - - 0 1").expect("Valid FEN");
+        let hash_move = ChessMove::new(Square::E1, Square::E2, None);
+        let killer = ChessMove::new(Square::C1, Square::A1, None);
+        let mut heuristics = Heuristics::new();
+        heuristics.update(&board, killer, 3, 0);
+
+        let moves: Vec<ChessMove> =
+            MovePicker::new(&board, Some(hash_move), &heuristics, 0).collect();
+        assert_eq!(moves.len(), MoveGen::new_legal(&board).len());
+        assert_eq!(moves[0], hash_move);
+        assert_eq!(moves[1], ChessMove::new(Square::B5, Square::C6, None));
+        assert_eq!(moves[2], killer);
+        assert_eq!(
+            moves.last(),
+            Some(&ChessMove::new(Square::C1, Square::C2, None))
+        );
+    }
+
+    #[test]
+    fn quiescence_moves() {
+        let board = Board::from_str("r3k3/1P6/2q5/8/8/8/2p5/2R1K3 w - - 0 1").expect("Valid FEN");
+        let moves: Vec<ChessMove> = MovePicker::new_quiescence(&board).collect();
+        assert!(moves.iter().all(|m| !is_quiet(&board, *m)));
+        assert!(moves.contains(&ChessMove::new(Square::C1, Square::C2, None)));
+        assert!(moves.contains(&ChessMove::new(Square::B7, Square::B8, Some(Piece::Queen))));
+        // capture with promotion comes first
+        assert_eq!(
+            moves[0],
+            ChessMove::new(Square::B7, Square::A8, Some(Piece::Queen))
+        );
+    }
+}
@@ -0,0 +1,269 @@
+// how a game ended, chess::GameResult has no notion of time
+use chess::{BitBoard, Board, Color, Game, GameResult, Piece, EMPTY};
+use std::fmt;
+
+use crate::history::game_moves;
+
+// the color is always the side that lost, or ran out of time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Checkmate(Color),
+    Stalemate,
+    // neither side can checkmate anymore
+    InsufficientMaterial,
+    Resignation(Color),
+    DrawAgreement,
+    // draws claimed by a player
+    Repetition,
+    FiftyMoves,
+    Timeout(Color),
+    // the flag fell, but the opponent can't checkmate
+    TimeoutDraw(Color),
+}
+
+impl Outcome {
+    // `start` is the position the game started from and `start_clock` its halfmove clock, to tell which draw was claimed
+    pub fn from_game(start: &Board, start_clock: u32, game: &Game) -> Option<Outcome> {
+        Some(match game.result() {
+            Some(GameResult::WhiteCheckmates) => Outcome::Checkmate(Color::Black),
+            Some(GameResult::BlackCheckmates) => Outcome::Checkmate(Color::White),
+            Some(GameResult::WhiteResigns) => Outcome::Resignation(Color::White),
+            Some(GameResult::BlackResigns) => Outcome::Resignation(Color::Black),
+            Some(GameResult::Stalemate) => Outcome::Stalemate,
+            Some(GameResult::DrawAccepted) => Outcome::DrawAgreement,
+            Some(GameResult::DrawDeclared) if halfmove_clock(start, start_clock, game) >= 100 => {
+                Outcome::FiftyMoves
+            }
+            Some(GameResult::DrawDeclared) => Outcome::Repetition,
+            // chess::Game plays on without mating material
+            None => {
+                let board = game.current_position();
+                if can_checkmate(&board, Color::White) || can_checkmate(&board, Color::Black) {
+                    return None;
+                }
+                Outcome::InsufficientMaterial
+            }
+        })
+    }
+
+    // `flagged` ran out of time in the position on the board
+    pub fn time_forfeit(board: &Board, flagged: Color) -> Outcome {
+        if can_checkmate(board, !flagged) {
+            Outcome::Timeout(flagged)
+        } else {
+            Outcome::TimeoutDraw(flagged)
+        }
+    }
+
+    pub fn winner(&self) -> Option<Color> {
+        match *self {
+            Outcome::Checkmate(loser) | Outcome::Resignation(loser) | Outcome::Timeout(loser) => {
+                Some(!loser)
+            }
+            _ => None,
+        }
+    }
+
+    // "1-0", "0-1" or "1/2-1/2"
+    pub fn result_tag(&self) -> &'static str {
+        match self.winner() {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Checkmate(loser) => write!(f, "Checkmate! Winner: {}", color_name(!loser)),
+            Outcome::Stalemate => write!(f, "Stalemate!"),
+            Outcome::Resignation(loser) => write!(
+                f,
+                "{} resigns! Winner: {}",
+                color_name(loser),
+                color_name(!loser)
+            ),
+            Outcome::InsufficientMaterial => write!(f, "Draw by insufficient material!"),
+            Outcome::DrawAgreement => write!(f, "Draw by agreement!"),
+            Outcome::Repetition => write!(f, "Draw by threefold repetition!"),
+            Outcome::FiftyMoves => write!(f, "Draw by the 50-move rule!"),
+            Outcome::Timeout(loser) => write!(
+                f,
+                "{} ran out of time! Winner: {}",
+                color_name(loser),
+                color_name(!loser)
+            ),
+            Outcome::TimeoutDraw(loser) => write!(
+                f,
+                "{} ran out of time, but {} can't checkmate. Draw!",
+                color_name(loser),
+                color_name(!loser)
+            ),
+        }
+    }
+}
+
+// plies since the last capture or pawn move, counting on from the halfmove clock of the starting position
+pub fn halfmove_clock(start: &Board, start_clock: u32, game: &Game) -> u32 {
+    let mut board = *start;
+    let mut clock = start_clock;
+    for m in game_moves(game) {
+        if board.piece_on(m.get_source()) == Some(Piece::Pawn)
+            || board.piece_on(m.get_dest()).is_some()
+        {
+            clock = 0;
+        } else {
+            clock += 1;
+        }
+        board = board.make_move_new(m);
+    }
+    clock
+}
+
+// whether `color` could still checkmate with any series of legal moves, to decide games lost on time.
+// a lone minor piece needs the opponent's pieces to block its king in, bishops of one square color
+// can't mate if those pieces are bishops on the same squares
+pub fn can_checkmate(board: &Board, color: Color) -> bool {
+    let own = *board.color_combined(color);
+    let other = *board.color_combined(!color);
+    let pieces = |piece: Piece, side: BitBoard| *board.pieces(piece) & side;
+    let heavy = pieces(Piece::Pawn, own) | pieces(Piece::Rook, own) | pieces(Piece::Queen, own);
+    if heavy != EMPTY {
+        return true;
+    }
+    let knights = pieces(Piece::Knight, own);
+    let bishops = pieces(Piece::Bishop, own);
+    let blockers = other & !pieces(Piece::King, other);
+    match (knights.popcnt(), bishops.popcnt()) {
+        (0, 0) => false,
+        (1, 0) => blockers != EMPTY,
+        (0, _) => {
+            const DARK: BitBoard = BitBoard(0xAA55AA55AA55AA55);
+            let all_bishops = bishops | pieces(Piece::Bishop, other);
+            let one_color = all_bishops & DARK == EMPTY || all_bishops & !DARK == EMPTY;
+            !(one_color && blockers & !pieces(Piece::Bishop, other) == EMPTY)
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{BoardStatus, ChessMove, MoveGen};
+    use std::str::FromStr;
+
+    fn can_mate(fen: &str, color: Color) -> bool {
+        can_checkmate(&Board::from_str(fen).unwrap(), color)
+    }
+
+    #[test]
+    fn mating_material() {
+        assert!(can_mate("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", Color::White));
+        assert!(!can_mate("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", Color::Black));
+        // a lone knight needs something to block the king in
+        assert!(!can_mate("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", Color::White));
+        assert!(can_mate("4k3/4p3/8/8/8/8/8/4KN2 w - - 0 1", Color::White));
+        // bishops on squares of one color
+        assert!(!can_mate("4k3/8/8/8/8/8/8/3BKB2 w - - 0 1", Color::White));
+        assert!(!can_mate("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1", Color::White));
+        assert!(can_mate("4kb2/8/8/8/8/8/8/4KB2 w - - 0 1", Color::White));
+        assert!(can_mate("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", Color::White));
+        assert!(can_mate("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", Color::White));
+    }
+
+    #[test]
+    fn outcomes() {
+        let board = Board::from_str("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert_eq!(
+            Outcome::time_forfeit(&board, Color::Black),
+            Outcome::Timeout(Color::Black)
+        );
+        assert_eq!(Outcome::Timeout(Color::Black).result_tag(), "1-0");
+        let draw = Outcome::time_forfeit(&board, Color::White);
+        assert_eq!(draw, Outcome::TimeoutDraw(Color::White));
+        assert_eq!(draw.result_tag(), "1/2-1/2");
+
+        let start = Board::default();
+        let mut game = Game::new();
+        assert_eq!(Outcome::from_game(&start, 0, &game), None);
+        game.resign(Color::White);
+        let outcome = Outcome::from_game(&start, 0, &game).unwrap();
+        assert_eq!(outcome.result_tag(), "0-1");
+        assert_eq!(outcome.to_string(), "White resigns! Winner: Black");
+
+        let game = Game::new_with_board(Board::from_str("4k3/8/8/8/8/8/8/3BK3 w - - 0 1").unwrap());
+        assert_eq!(
+            Outcome::from_game(&game.current_position(), 0, &game),
+            Some(Outcome::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn claimed_draws() {
+        let start = Board::default();
+        let mut game = Game::new();
+        for _ in 0..2 {
+            for m in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                game.make_move(ChessMove::from_str(m).unwrap());
+            }
+        }
+        assert!(game.declare_draw());
+        assert_eq!(
+            Outcome::from_game(&start, 0, &game),
+            Some(Outcome::Repetition)
+        );
+
+        // 50 moves without captures or pawn moves, without repeating a position
+        let start = Board::from_str("r6k/8/8/8/8/8/8/R6K w - - 0 1").unwrap();
+        let mut game = Game::new_with_board(start);
+        let mut board = start;
+        let mut seen = vec![board.get_hash()];
+        for _ in 0..100 {
+            let m = MoveGen::new_legal(&board)
+                .find(|m| {
+                    let next = board.make_move_new(*m);
+                    board.piece_on(m.get_dest()).is_none()
+                        && next.status() == BoardStatus::Ongoing
+                        && !seen.contains(&next.get_hash())
+                })
+                .unwrap();
+            game.make_move(m);
+            board = board.make_move_new(m);
+            seen.push(board.get_hash());
+        }
+        assert_eq!(halfmove_clock(&start, 0, &game), 100);
+        assert!(game.declare_draw());
+        assert_eq!(
+            Outcome::from_game(&start, 0, &game),
+            Some(Outcome::FiftyMoves)
+        );
+
+        // a repetition that completes the fifty moves of a game set up with a halfmove clock
+        let start = Board::from_str("r6k/8/8/8/8/8/8/R6K w - - 92 1").unwrap();
+        let mut game = Game::new_with_board(start);
+        for _ in 0..2 {
+            for m in ["a1a2", "a8a7", "a2a1", "a7a8"] {
+                game.make_move(ChessMove::from_str(m).unwrap());
+            }
+        }
+        assert!(game.declare_draw());
+        assert_eq!(
+            Outcome::from_game(&start, 0, &game),
+            Some(Outcome::Repetition)
+        );
+        assert_eq!(
+            Outcome::from_game(&start, 92, &game),
+            Some(Outcome::FiftyMoves)
+        );
+    }
+}
@@ -0,0 +1,477 @@
+// PGN (portable game notation) export and import, so games can go into and come from standard databases
+use chess::{Board, ChessMove, Color, Game};
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::engine::to_fen;
+use crate::history::game_moves;
+use crate::outcome::Outcome;
+use crate::san::{parse_san, to_san};
+use crate::{Score, SearchResult};
+
+// lines of the movetext are wrapped at this length, as in the PGN export format
+const LINE_LENGTH: usize = 80;
+
+// the seven tag roster, the result tag is taken from the game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnHeader {
+    pub event: String,
+    pub site: String,
+    pub date: String,
+    pub round: String,
+    pub white: String,
+    pub black: String,
+}
+
+impl Default for PgnHeader {
+    fn default() -> PgnHeader {
+        PgnHeader {
+            event: "?".to_string(),
+            site: "?".to_string(),
+            date: "????.??.??".to_string(),
+            round: "?".to_string(),
+            white: "?".to_string(),
+            black: "?".to_string(),
+        }
+    }
+}
+
+impl PgnHeader {
+    // header for a casual game played today
+    pub fn new(white: &str, black: &str) -> PgnHeader {
+        PgnHeader {
+            event: "Casual game".to_string(),
+            date: today(),
+            white: white.to_string(),
+            black: black.to_string(),
+            ..PgnHeader::default()
+        }
+    }
+}
+
+// current date in the PGN format, yyyy.mm.dd
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86400) as i64;
+    // civil date from days since 1970-01-01, proleptic gregorian calendar
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+// "1-0", "0-1", "1/2-1/2", or "*" while the game is still going on
+pub fn result_tag(outcome: Option<Outcome>) -> &'static str {
+    outcome.map_or("*", |outcome| outcome.result_tag())
+}
+
+// number of the move at the given ply, counting from the start of the game,
+// so in games starting with black to move, black's first move is 1... as well
+pub fn move_number(start: &Board, ply: usize) -> usize {
+    (ply + start.side_to_move().to_index()) / 2 + 1
+}
+
+// a move in SAN with its number, like 12. Nf3 or 12... Nf6. `board` is the position before the move
+pub fn numbered_move(start: &Board, ply: usize, board: &Board, m: ChessMove) -> String {
+    let dots = match board.side_to_move() {
+        Color::White => ".",
+        Color::Black => "...",
+    };
+    format!("{}{} {}", move_number(start, ply), dots, to_san(board, m))
+}
+
+fn tag(name: &str, value: &str) -> String {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("[{} \"{}\"]\n", name, value)
+}
+
+// score and depth of the search behind a move, from the point of view of the side that moved
+fn comment(result: &SearchResult) -> String {
+    let score = match result.score {
+        Score::Centipawns(cp) => format!("{:+.2}", cp as f64 / 100.0),
+        Score::Mate(n) if n > 0 => format!("+M{}", n),
+        Score::Mate(n) => format!("-M{}", -n),
+    };
+    format!("{{{}/{}}}", score, result.depth)
+}
+
+// the game as PGN, `start` is the position the game started from, which chess::Game doesn't expose.
+// `outcome` is passed separately since the game doesn't know about clocks.
+// `searches` holds the search behind every move by ply, moves without one get no comment
+pub fn to_pgn(
+    header: &PgnHeader,
+    start: &Board,
+    game: &Game,
+    outcome: Option<Outcome>,
+    searches: &[Option<SearchResult>],
+) -> String {
+    let result = result_tag(outcome);
+    let mut pgn = String::new();
+    pgn.push_str(&tag("Event", &header.event));
+    pgn.push_str(&tag("Site", &header.site));
+    pgn.push_str(&tag("Date", &header.date));
+    pgn.push_str(&tag("Round", &header.round));
+    pgn.push_str(&tag("White", &header.white));
+    pgn.push_str(&tag("Black", &header.black));
+    pgn.push_str(&tag("Result", result));
+    if let Some(Outcome::Timeout(_) | Outcome::TimeoutDraw(_)) = outcome {
+        pgn.push_str(&tag("Termination", "time forfeit"));
+    }
+    if *start != Board::default() {
+        pgn.push_str(&tag("SetUp", "1"));
+        pgn.push_str(&tag("FEN", &to_fen(start)));
+    }
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut board = *start;
+    for (ply, m) in game_moves(game).into_iter().enumerate() {
+        let number = move_number(start, ply);
+        if board.side_to_move() == Color::White {
+            tokens.push(format!("{}.", number));
+        } else if ply == 0 {
+            tokens.push(format!("{}...", number));
+        }
+        tokens.push(to_san(&board, m));
+        if let Some(Some(search)) = searches.get(ply) {
+            tokens.push(comment(search));
+        }
+        board = board.make_move_new(m);
+    }
+    tokens.push(result.to_string());
+
+    // wrap the movetext
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push_str("\n\n");
+    pgn
+}
+
+// a game read from a PGN file
+#[derive(Clone, Debug)]
+pub struct PgnGame {
+    // all tags, in the order of the file
+    pub tags: Vec<(String, String)>,
+    // the starting position, from the FEN tag if there is one
+    pub start: Board,
+    // the moves of the main line, the result of the game is only in the tags
+    pub game: Game,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn moves(&self) -> Vec<ChessMove> {
+        game_moves(&self.game)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Move(String),
+    Result,
+}
+
+// read the next tag, comment, variation, move or result of the movetext. Comments, NAGs, move numbers and variations are skipped
+fn next_token(chars: &mut Peekable<Chars>) -> Result<Option<Token>, String> {
+    // nesting depth of variations
+    let mut depth = 0;
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            c if c.is_whitespace() => {}
+            '{' => {
+                if !chars.any(|c| c == '}') {
+                    return Err("Unterminated comment".to_string());
+                }
+            }
+            // comments to the end of the line, and escaped lines
+            ';' => {
+                chars.find(|c| *c == '\n');
+            }
+            '%' if line_start => {
+                chars.find(|c| *c == '\n');
+            }
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return Err("Unexpected )".to_string()),
+            '[' if depth == 0 => {
+                let tag: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err("Unterminated tag".to_string()),
+                    }
+                }
+                if !chars.any(|c| c == ']') {
+                    return Err("Unterminated tag".to_string());
+                }
+                return Ok(Some(Token::Tag(tag.trim().to_string(), value)));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || "{}()[];".contains(*c) {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                if depth > 0 {
+                    continue;
+                }
+                match word.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => return Ok(Some(Token::Result)),
+                    w if w.starts_with('$') => {}
+                    w => {
+                        // a move number, possibly followed by the move without a space
+                        let w = if w.starts_with(|c: char| c.is_ascii_digit()) && w.contains('.') {
+                            w.trim_start_matches(|c: char| c.is_ascii_digit())
+                                .trim_start_matches('.')
+                        } else {
+                            w
+                        };
+                        if !w.is_empty() {
+                            return Ok(Some(Token::Move(w.to_string())));
+                        }
+                    }
+                }
+            }
+        }
+        line_start = false;
+    }
+    Ok(None)
+}
+
+// a game while it's read
+struct GameReader {
+    tags: Vec<(String, String)>,
+    start: Board,
+    game: Option<Game>,
+}
+
+impl GameReader {
+    fn new() -> GameReader {
+        GameReader {
+            tags: Vec::new(),
+            start: Board::default(),
+            game: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.game.is_none()
+    }
+
+    fn finish(self) -> PgnGame {
+        PgnGame {
+            game: self
+                .game
+                .unwrap_or_else(|| Game::new_with_board(self.start)),
+            tags: self.tags,
+            start: self.start,
+        }
+    }
+}
+
+// read all games of a PGN file, errors name the game and the ply where reading failed
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, String> {
+    let mut games = Vec::new();
+    let mut current = GameReader::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        let number = games.len() + 1;
+        let ply = current.game.as_ref().map_or(0, |g| game_moves(g).len());
+        let token = next_token(&mut chars).map_err(|e| format!("Game {}: {}", number, e))?;
+        match token {
+            Some(Token::Tag(name, value)) => {
+                // tags after moves start the next game, even if the result is missing
+                if current.game.is_some() {
+                    games.push(std::mem::replace(&mut current, GameReader::new()).finish());
+                }
+                if name == "FEN" {
+                    current.start = Board::from_str(&value)
+                        .map_err(|_| format!("Game {}: Invalid FEN: {}", number, value))?;
+                }
+                current.tags.push((name, value));
+            }
+            Some(Token::Move(san)) => {
+                let start = current.start;
+                let game = current
+                    .game
+                    .get_or_insert_with(|| Game::new_with_board(start));
+                let m = parse_san(&game.current_position(), &san)
+                    .map_err(|e| format!("Game {}, ply {}: {}", number, ply + 1, e))?;
+                game.make_move(m);
+            }
+            Some(Token::Result) => {
+                games.push(std::mem::replace(&mut current, GameReader::new()).finish());
+            }
+            None => break,
+        }
+    }
+    if !current.is_empty() {
+        games.push(current.finish());
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::ChessMove;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for m in moves {
+            assert!(game.make_move(ChessMove::from_str(m).unwrap()));
+        }
+    }
+
+    #[test]
+    fn finished_game() {
+        let mut game = Game::new();
+        play(&mut game, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let mut header = PgnHeader::new("Fool", "chess-ai");
+        header.date = "2024.01.02".to_string();
+        let search = SearchResult {
+            best_move: None,
+            score: Score::Mate(1),
+            depth: 4,
+            nodes: 0,
+            qnodes: 0,
+            time: Duration::ZERO,
+            pv: Vec::new(),
+        };
+        let searches = vec![None, None, None, Some(search)];
+        let pgn = to_pgn(
+            &header,
+            &Board::default(),
+            &game,
+            Outcome::from_game(&Board::default(), 0, &game),
+            &searches,
+        );
+        assert_eq!(
+            pgn,
+            "[Event \"Casual game\"]\n[Site \"?\"]\n[Date \"2024.01.02\"]\n[Round \"?\"]\n\
+             [White \"Fool\"]\n[Black \"chess-ai\"]\n[Result \"0-1\"]\n\n\
+             1. f3 e5 2. g4 Qh4# {+M1/4} 0-1\n\n"
+        );
+    }
+
+    #[test]
+    fn custom_start_in_progress() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1";
+        let start = Board::from_str(fen).unwrap();
+        let mut game = Game::new_with_board(start);
+        play(&mut game, &["e8d7", "e2e4", "d7e6"]);
+        let pgn = to_pgn(&PgnHeader::default(), &start, &game, None, &[]);
+        assert!(pgn.contains(
+            "[Result \"*\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n"
+        ));
+        assert!(pgn.ends_with("\n1... Kd7 2. e4 Ke6 *\n\n"));
+        let board = start.make_move_new(ChessMove::from_str("e8d7").unwrap());
+        let m = ChessMove::from_str("e2e4").unwrap();
+        assert_eq!(numbered_move(&start, 1, &board, m), "2. e4");
+    }
+
+    #[test]
+    fn long_games_are_wrapped() {
+        let mut game = Game::new();
+        for _ in 0..10 {
+            play(&mut game, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        }
+        // lost on time
+        let outcome = Some(Outcome::Timeout(Color::White));
+        let pgn = to_pgn(
+            &PgnHeader::default(),
+            &Board::default(),
+            &game,
+            outcome,
+            &[],
+        );
+        assert!(pgn.contains("[Result \"0-1\"]\n[Termination \"time forfeit\"]\n"));
+        assert!(pgn.ends_with(" 0-1\n\n"));
+        let movetext: Vec<&str> = pgn.split("\n\n").nth(1).unwrap().lines().collect();
+        assert!(movetext.len() > 1);
+        assert!(movetext.iter().all(|l| l.len() <= LINE_LENGTH));
+    }
+
+    #[test]
+    fn read_games() {
+        let text = "[Event \"Test \\\"quoted\\\"\"]\n[White \"A\"]\n\n\
+             1. e4 {best by test} e5 2.Nf3 $1 (2. f4 exf4 (2... d5) 3. Nf3) Nc6 3. Bb5 a6!? ; comment\n\
+             4. Ba4 1-0\n\n\
+             [Event \"Second\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1\"]\n\n\
+             1. exd6 Kd7 *\n";
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("Test \"quoted\""));
+        assert_eq!(games[0].tag("White"), Some("A"));
+        let moves: Vec<String> = games[0].moves().iter().map(|m| m.to_string()).collect();
+        assert_eq!(
+            moves,
+            vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4"]
+        );
+        assert_eq!(games[1].moves().len(), 2);
+        assert_eq!(to_fen(&games[1].start), "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+
+        // export and import again
+        let pgn = to_pgn(
+            &PgnHeader::default(),
+            &games[1].start,
+            &games[1].game,
+            None,
+            &[],
+        );
+        let again = parse_pgn(&pgn).unwrap();
+        assert_eq!(again[0].moves(), games[1].moves());
+    }
+
+    #[test]
+    fn errors_name_game_and_ply() {
+        let text = "1. e4 e5 *\n\n1. d4 d5 2. Nf3 Nf6 3. Nd2 *\n";
+        assert_eq!(
+            parse_pgn(text).unwrap_err(),
+            "Game 2, ply 5: Ambiguous move: Nd2"
+        );
+        let text = "1. e4 e5 2. Ke3 *";
+        assert_eq!(
+            parse_pgn(text).unwrap_err(),
+            "Game 1, ply 3: Illegal move: Ke3"
+        );
+    }
+}
@@ -0,0 +1,407 @@
+// players of a game: the bot, external engines and scripted moves
+// front ends (commandline, gui) drive any implementor of the trait, humans are implemented there
+use chess::{Board, ChessMove, Game};
+use std::collections::VecDeque;
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::engine::{Position, SearchLimits};
+use crate::external::{EngineStop, ExternalEngine};
+use crate::history::game_moves;
+use crate::record::draw_offered;
+use crate::{Bot, History, Score, SearchHandle, SearchResult};
+
+// bots and engines accept a draw offer when their search says they are at least this much worse, in centipawns
+const ACCEPT_DRAW_SCORE: i32 = -50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerAction {
+    Move(ChessMove),
+    // no move yet, ask again later
+    Wait,
+    Resign,
+    // take back moves, or make them again, for humans
+    Undo,
+    Redo,
+    // offer a draw before making a move
+    OfferDraw,
+    // answer the draw the opponent offered with its last move
+    AcceptDraw,
+    DeclineDraw,
+    // claim a draw by threefold repetition or the 50-move rule
+    ClaimDraw,
+}
+
+pub trait Player {
+    fn name(&self) -> String;
+
+    // called before the first move, with the starting position of the game and the halfmove clock from its FEN
+    fn new_game(&mut self, _start: &Board, _halfmove_clock: u32) {}
+
+    // asked repeatedly while it's the player's turn, players used in the gui must not block for long
+    fn choose_move(&mut self, game: &Game) -> PlayerAction;
+
+    // whether the player is a human entering moves in the front end
+    fn accepts_input(&self) -> bool {
+        false
+    }
+
+    // a move entered on the board of the gui
+    fn input(&mut self, _m: ChessMove) {}
+
+    // move now, with the best move found so far
+    fn stop(&mut self) {}
+
+    // the time on the player's clock, given before its turn in games with a clock
+    fn set_clock(&mut self, _limits: SearchLimits) {}
+
+    // the search behind the last move, for players that search
+    fn search_result(&self) -> Option<&SearchResult> {
+        None
+    }
+
+    // why the player resigned, for players that can fail, the front ends show it
+    fn error(&self) -> Option<&str> {
+        None
+    }
+}
+
+// ask the player until it decides, for front ends that can block
+pub fn wait_for_action(player: &mut dyn Player, game: &Game) -> PlayerAction {
+    loop {
+        match player.choose_move(game) {
+            PlayerAction::Wait => thread::sleep(Duration::from_millis(5)),
+            action => return action,
+        }
+    }
+}
+
+// the action of a searching player, once the search is done
+fn search_action(game: &Game, result: &SearchResult) -> PlayerAction {
+    let worse = match result.score {
+        Score::Centipawns(cp) => cp <= ACCEPT_DRAW_SCORE,
+        Score::Mate(n) => n < 0,
+    };
+    match result.best_move {
+        None => PlayerAction::Resign,
+        Some(_) if worse && draw_offered(game) => PlayerAction::AcceptDraw,
+        Some(m) => PlayerAction::Move(m),
+    }
+}
+
+// the bot, searching in the background
+pub struct BotPlayer {
+    // None while searching
+    bot: Option<Bot>,
+    search: Option<SearchHandle>,
+    start: Board,
+    // of the starting position, the board doesn't keep it
+    halfmove_clock: u32,
+    result: Option<SearchResult>,
+    // the depth the bot was configured with, the time limit comes from the clock if there is one
+    depth: u8,
+    clock: Option<SearchLimits>,
+}
+
+impl BotPlayer {
+    pub fn new(bot: Bot) -> BotPlayer {
+        BotPlayer {
+            depth: bot.depth(),
+            bot: Some(bot),
+            search: None,
+            start: Board::default(),
+            halfmove_clock: 0,
+            result: None,
+            clock: None,
+        }
+    }
+}
+
+impl Player for BotPlayer {
+    fn name(&self) -> String {
+        "chess-ai".to_string()
+    }
+
+    fn new_game(&mut self, start: &Board, halfmove_clock: u32) {
+        self.start = *start;
+        self.halfmove_clock = halfmove_clock;
+        if let Some(bot) = &mut self.bot {
+            bot.new_game();
+        }
+    }
+
+    fn choose_move(&mut self, game: &Game) -> PlayerAction {
+        if let Some(search) = &self.search {
+            if !search.is_finished() {
+                return PlayerAction::Wait;
+            }
+            let (bot, result) = self.search.take().expect("A search is running").wait();
+            self.bot = Some(bot);
+            let action = search_action(game, &result);
+            self.result = Some(result);
+            return action;
+        }
+        let mut bot = self
+            .bot
+            .take()
+            .expect("The bot is only missing while searching");
+        if let Some(limits) = self.clock.take() {
+            limits.apply(&mut bot, self.depth);
+        }
+        let history = History::from_game(&self.start, self.halfmove_clock, game);
+        self.search = Some(bot.start_search(game.current_position(), history));
+        PlayerAction::Wait
+    }
+
+    fn stop(&mut self) {
+        if let Some(search) = &self.search {
+            search.stop();
+        }
+    }
+
+    fn set_clock(&mut self, limits: SearchLimits) {
+        self.clock = Some(limits);
+    }
+
+    fn search_result(&self) -> Option<&SearchResult> {
+        self.result.as_ref()
+    }
+}
+
+type EngineSearch = JoinHandle<(ExternalEngine, io::Result<SearchResult>)>;
+
+// an external UCI engine, asked on a background thread
+pub struct EnginePlayer {
+    // None while searching
+    engine: Option<ExternalEngine>,
+    search: Option<EngineSearch>,
+    // sends stop while the engine is on the search thread
+    stopper: EngineStop,
+    name: String,
+    start: Board,
+    // of the starting position, the board doesn't keep it
+    halfmove_clock: u32,
+    result: Option<SearchResult>,
+    clock: Option<SearchLimits>,
+    // the engine failed and resigns
+    error: Option<String>,
+}
+
+impl EnginePlayer {
+    pub fn new(engine: ExternalEngine) -> EnginePlayer {
+        EnginePlayer {
+            name: engine.name().to_string(),
+            stopper: engine.stopper(),
+            engine: Some(engine),
+            search: None,
+            start: Board::default(),
+            halfmove_clock: 0,
+            result: None,
+            clock: None,
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, e: io::Error) {
+        self.error = Some(format!("{} failed: {}", self.name, e));
+    }
+}
+
+impl Player for EnginePlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn new_game(&mut self, start: &Board, halfmove_clock: u32) {
+        self.start = *start;
+        self.halfmove_clock = halfmove_clock;
+        if let Some(engine) = &mut self.engine {
+            if let Err(e) = engine.new_game() {
+                self.fail(e);
+            }
+        }
+    }
+
+    fn choose_move(&mut self, game: &Game) -> PlayerAction {
+        if let Some(search) = &self.search {
+            if !search.is_finished() {
+                return PlayerAction::Wait;
+            }
+            let search = self.search.take().expect("A search is running");
+            let (engine, result) = search.join().expect("Engine thread panicked");
+            self.engine = Some(engine);
+            return match result {
+                Ok(result) => {
+                    let action = search_action(game, &result);
+                    self.result = Some(result);
+                    action
+                }
+                Err(e) => {
+                    self.fail(e);
+                    PlayerAction::Resign
+                }
+            };
+        }
+        if self.error.is_some() {
+            return PlayerAction::Resign;
+        }
+        let mut position = Position::new(self.start, self.halfmove_clock);
+        for m in game_moves(game) {
+            position.make_move(m).expect("Moves of the game are legal");
+        }
+        let mut engine = self
+            .engine
+            .take()
+            .expect("The engine is only missing while searching");
+        let clock = self.clock.take();
+        self.search = Some(thread::spawn(move || {
+            let result = engine.get_move(&position, clock.as_ref());
+            (engine, result)
+        }));
+        PlayerAction::Wait
+    }
+
+    fn stop(&mut self) {
+        if self
+            .search
+            .as_ref()
+            .is_some_and(|search| !search.is_finished())
+        {
+            if let Err(e) = self.stopper.stop() {
+                self.fail(e);
+            }
+        }
+    }
+
+    fn set_clock(&mut self, limits: SearchLimits) {
+        self.clock = Some(limits);
+    }
+
+    fn search_result(&self) -> Option<&SearchResult> {
+        self.result.as_ref()
+    }
+
+    fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+// plays a fixed list of moves, and resigns when they run out or one is illegal
+pub struct ScriptedPlayer {
+    moves: VecDeque<ChessMove>,
+}
+
+impl ScriptedPlayer {
+    pub fn new(moves: Vec<ChessMove>) -> ScriptedPlayer {
+        ScriptedPlayer {
+            moves: moves.into(),
+        }
+    }
+}
+
+impl Player for ScriptedPlayer {
+    fn name(&self) -> String {
+        "Scripted".to_string()
+    }
+
+    fn choose_move(&mut self, game: &Game) -> PlayerAction {
+        match self.moves.pop_front() {
+            Some(m) if game.current_position().legal(m) => PlayerAction::Move(m),
+            _ => PlayerAction::Resign,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Color, GameResult};
+    use std::str::FromStr;
+
+    fn moves(moves: &[&str]) -> Vec<ChessMove> {
+        moves
+            .iter()
+            .map(|m| ChessMove::from_str(m).unwrap())
+            .collect()
+    }
+
+    // play until the game ends, the way the commandline does
+    fn play(mut game: Game, white: &mut dyn Player, black: &mut dyn Player) -> Game {
+        white.new_game(&game.current_position(), 0);
+        black.new_game(&game.current_position(), 0);
+        while game.result().is_none() {
+            let color = game.side_to_move();
+            let player: &mut dyn Player = match color {
+                Color::White => &mut *white,
+                Color::Black => &mut *black,
+            };
+            match wait_for_action(player, &game) {
+                PlayerAction::Move(m) => assert!(game.make_move(m)),
+                PlayerAction::Resign => assert!(game.resign(color)),
+                _ => unreachable!(),
+            }
+        }
+        game
+    }
+
+    #[test]
+    fn scripted_players() {
+        let mut white = ScriptedPlayer::new(moves(&["f2f3", "g2g4"]));
+        let mut black = ScriptedPlayer::new(moves(&["e7e5", "d8h4"]));
+        let game = play(Game::new(), &mut white, &mut black);
+        assert_eq!(game.result(), Some(GameResult::BlackCheckmates));
+
+        // out of moves
+        let mut white = ScriptedPlayer::new(moves(&["e2e4"]));
+        let mut black = ScriptedPlayer::new(moves(&["e7e5"]));
+        let game = play(Game::new(), &mut white, &mut black);
+        assert_eq!(game.result(), Some(GameResult::WhiteResigns));
+    }
+
+    #[test]
+    fn bot_player() {
+        let game = Game::from_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut white = BotPlayer::new(Bot::new(Color::White, 3));
+        let mut black = ScriptedPlayer::new(Vec::new());
+        let game = play(game, &mut white, &mut black);
+        assert_eq!(game.result(), Some(GameResult::WhiteCheckmates));
+        let result = white.search_result().expect("The bot searched");
+        assert_eq!(result.best_move, Some(ChessMove::from_str("a1a8").unwrap()));
+    }
+
+    #[test]
+    fn bots_accept_draws_when_worse() {
+        let offer = |fen: &str| {
+            let mut game = Game::from_str(fen).unwrap();
+            let color = game.side_to_move();
+            game.offer_draw(!color);
+            let mut bot = BotPlayer::new(Bot::new(color, 3));
+            bot.new_game(&game.current_position(), 0);
+            wait_for_action(&mut bot, &game)
+        };
+        // a queen down, and a queen up
+        let worse = "3qk3/8/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(offer(worse), PlayerAction::AcceptDraw);
+        let better = "3qk3/8/8/8/8/8/8/4K3 b - - 0 1";
+        assert!(matches!(offer(better), PlayerAction::Move(_)));
+    }
+
+    #[test]
+    fn bot_player_uses_its_clock() {
+        let game = Game::new();
+        // far too deep to finish without the time limit from the clock
+        let mut bot = BotPlayer::new(Bot::new(Color::White, 40));
+        bot.new_game(&game.current_position(), 0);
+        bot.set_clock(SearchLimits {
+            time_left: Some(Duration::from_secs(2)),
+            ..Default::default()
+        });
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            wait_for_action(&mut bot, &game),
+            PlayerAction::Move(_)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,334 @@
+// a game as the front ends keep it: the moves with the searches behind them, and moves taken back
+use chess::{Action, Board, ChessMove, Color, Game};
+
+use crate::history::{game_moves, repetitions};
+use crate::outcome::Outcome;
+use crate::{History, SearchResult};
+
+// what a takeback undoes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UndoPolicy {
+    // a single ply
+    Ply,
+    // also the reply of a player that doesn't enter moves, so a human is to move again
+    #[default]
+    WithReply,
+}
+
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    // chess::Game doesn't expose the position it started from
+    start: Board,
+    // the halfmove clock of the starting position, from its FEN
+    halfmove_clock: u32,
+    game: Game,
+    // the positions since the start, for draw claims
+    history: History,
+    // chess::Game counts the 50 moves from 0 and can't record the claims of games set up with a halfmove clock
+    claimed: bool,
+    // the search behind every move by ply, None for moves without one
+    searches: Vec<Option<SearchResult>>,
+    // moves taken back, the last one is redone first
+    undone: Vec<(ChessMove, Option<SearchResult>)>,
+    // whether the side to move declined the draw offered to it
+    declined: bool,
+}
+
+// whether the opponent of the side to move offered a draw with its last move
+pub fn draw_offered(game: &Game) -> bool {
+    let offer = Action::OfferDraw(!game.side_to_move());
+    let actions = game.actions();
+    game.result().is_none()
+        && match actions.as_slice() {
+            [.., last] if *last == offer => true,
+            [.., offered, Action::MakeMove(_)] => *offered == offer,
+            _ => false,
+        }
+}
+
+impl GameRecord {
+    pub fn new(game: Game, halfmove_clock: u32) -> GameRecord {
+        let start = game.current_position();
+        let searches = vec![None; game_moves(&game).len()];
+        GameRecord {
+            start,
+            halfmove_clock,
+            game,
+            history: History::new(&start, halfmove_clock),
+            claimed: false,
+            searches,
+            undone: Vec::new(),
+            declined: false,
+        }
+    }
+
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    pub fn start_halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // how the game ended, None while it goes on
+    pub fn outcome(&self) -> Option<Outcome> {
+        if !self.claimed {
+            return Outcome::from_game(&self.start, self.halfmove_clock, &self.game);
+        }
+        Some(if self.history.halfmove_clock() >= 100 {
+            Outcome::FiftyMoves
+        } else {
+            Outcome::Repetition
+        })
+    }
+
+    fn finished(&self) -> bool {
+        self.claimed || self.game.result().is_some()
+    }
+
+    pub fn searches(&self) -> &[Option<SearchResult>] {
+        &self.searches
+    }
+
+    // number of moves played
+    pub fn ply(&self) -> usize {
+        self.searches.len()
+    }
+
+    // make a move, the moves taken back can't be redone anymore
+    pub fn make_move(&mut self, m: ChessMove, search: Option<SearchResult>) -> bool {
+        if !self.push_move(m) {
+            return false;
+        }
+        self.searches.push(search);
+        self.undone.clear();
+        self.declined = false;
+        true
+    }
+
+    fn push_move(&mut self, m: ChessMove) -> bool {
+        let board = self.game.current_position();
+        if self.claimed || !self.game.make_move(m) {
+            return false;
+        }
+        self.history.push(&board, m);
+        true
+    }
+
+    pub fn resign(&mut self, color: Color) -> bool {
+        !self.claimed && self.game.resign(color)
+    }
+
+    // offer a draw before making a move, the opponent can accept it on its turn
+    pub fn offer_draw(&mut self, color: Color) -> bool {
+        color == self.game.side_to_move() && !self.claimed && self.game.offer_draw(color)
+    }
+
+    // whether the side to move can accept a draw
+    pub fn draw_offered(&self) -> bool {
+        !self.declined && !self.claimed && draw_offered(&self.game)
+    }
+
+    pub fn accept_draw(&mut self) -> bool {
+        self.draw_offered() && self.game.accept_draw()
+    }
+
+    // false if there was no draw offer to decline
+    pub fn decline_draw(&mut self) -> bool {
+        let offered = self.draw_offered();
+        self.declined = true;
+        offered
+    }
+
+    // whether the side to move can claim a draw by threefold repetition or the 50-move rule
+    pub fn can_claim_draw(&self) -> bool {
+        !self.finished()
+            && (self.history.halfmove_clock() >= 100
+                || repetitions(self.history.hashes(), self.history.halfmove_clock()) >= 2)
+    }
+
+    pub fn claim_draw(&mut self) -> bool {
+        if !self.can_claim_draw() {
+            return false;
+        }
+        self.claimed = true;
+        true
+    }
+
+    // take back the last move, or the resignation or draw that ended the game
+    pub fn undo(&mut self) -> bool {
+        let mut moves = game_moves(&self.game);
+        let ended_by_action = self.claimed
+            || self.game.result().is_some()
+                && !matches!(self.game.actions().last(), Some(Action::MakeMove(_)));
+        if !ended_by_action {
+            let m = match moves.pop() {
+                Some(m) => m,
+                None => return false,
+            };
+            let search = self.searches.pop().flatten();
+            self.undone.push((m, search));
+        }
+        // rebuild the game from its moves, chess::Game can't take back actions
+        self.declined = false;
+        self.claimed = false;
+        self.game = Game::new_with_board(self.start);
+        self.history = History::new(&self.start, self.halfmove_clock);
+        for m in moves {
+            self.push_move(m);
+        }
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if self.finished() {
+            return false;
+        }
+        match self.undone.pop() {
+            Some((m, search)) => {
+                self.push_move(m);
+                self.searches.push(search);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // take back moves following the policy, `enters_moves` tells whether the player of a color is a human.
+    // Returns the number of plies taken back
+    pub fn undo_with(&mut self, policy: UndoPolicy, enters_moves: impl Fn(Color) -> bool) -> usize {
+        self.step_with(policy, enters_moves, GameRecord::undo)
+    }
+
+    pub fn redo_with(&mut self, policy: UndoPolicy, enters_moves: impl Fn(Color) -> bool) -> usize {
+        self.step_with(policy, enters_moves, GameRecord::redo)
+    }
+
+    fn step_with(
+        &mut self,
+        policy: UndoPolicy,
+        enters_moves: impl Fn(Color) -> bool,
+        step: fn(&mut GameRecord) -> bool,
+    ) -> usize {
+        let mut plies = 0;
+        while step(self) {
+            plies += 1;
+            if policy == UndoPolicy::Ply
+                || enters_moves(self.game.side_to_move())
+                || self.finished()
+            {
+                break;
+            }
+        }
+        plies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn record(moves: &[&str]) -> GameRecord {
+        let mut record = GameRecord::new(Game::new(), 0);
+        for m in moves {
+            assert!(record.make_move(ChessMove::from_str(m).unwrap(), None));
+        }
+        record
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut record = record(&["e2e4", "e7e5", "g1f3"]);
+        let position = record.game().current_position();
+        assert!(record.undo());
+        assert!(record.undo());
+        assert_eq!(record.ply(), 1);
+        assert!(record.redo());
+        assert!(record.redo());
+        assert!(!record.redo());
+        assert_eq!(record.game().current_position(), position);
+
+        // a new move drops the moves taken back
+        assert!(record.undo());
+        assert!(record.make_move(ChessMove::from_str("d2d4").unwrap(), None));
+        assert!(!record.redo());
+
+        // resignations are taken back on their own
+        record.resign(Color::Black);
+        assert!(record.undo());
+        assert_eq!(record.game().result(), None);
+        assert_eq!(record.ply(), 3);
+        assert!(record.undo() && record.undo() && record.undo());
+        assert!(!record.undo());
+    }
+
+    #[test]
+    fn draw_offers() {
+        let mut record = record(&["e2e4"]);
+        // only before the own move
+        assert!(!record.offer_draw(Color::White));
+        assert!(record.offer_draw(Color::Black));
+        assert!(!record.draw_offered());
+        assert!(record.make_move(ChessMove::from_str("e7e5").unwrap(), None));
+        assert!(record.draw_offered());
+        assert!(record.decline_draw());
+        assert!(!record.accept_draw());
+        // the offer ends with the next move
+        assert!(record.offer_draw(Color::White));
+        assert!(record.make_move(ChessMove::from_str("g1f3").unwrap(), None));
+        assert!(record.make_move(ChessMove::from_str("b8c6").unwrap(), None));
+        assert!(!record.draw_offered());
+
+        assert!(record.offer_draw(Color::White));
+        assert!(record.make_move(ChessMove::from_str("f1c4").unwrap(), None));
+        assert!(record.accept_draw());
+        assert_eq!(record.outcome(), Some(Outcome::DrawAgreement));
+        assert!(!record.claim_draw());
+    }
+
+    #[test]
+    fn draw_claims() {
+        let mut record = record(&["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"]);
+        assert!(!record.claim_draw());
+        assert!(record.make_move(ChessMove::from_str("f6g8").unwrap(), None));
+        assert!(record.claim_draw());
+        assert_eq!(record.outcome(), Some(Outcome::Repetition));
+        assert!(!record.claim_draw());
+        assert!(!record.make_move(ChessMove::from_str("g1f3").unwrap(), None));
+        // the claim is taken back on its own
+        assert!(record.undo());
+        assert_eq!(record.outcome(), None);
+        assert_eq!(record.ply(), 8);
+
+        // the 50 moves count on from the halfmove clock of the FEN
+        let start = Game::from_str("r6k/8/8/8/8/8/8/R6K w - - 92 1").unwrap();
+        let mut record = GameRecord::new(start, 92);
+        for m in ["a1b1", "a8b8", "b1c1", "b8c8", "c1d1", "c8d8", "d1e1"] {
+            assert!(record.make_move(ChessMove::from_str(m).unwrap(), None));
+        }
+        assert!(!record.claim_draw());
+        assert!(record.make_move(ChessMove::from_str("d8e8").unwrap(), None));
+        assert!(record.claim_draw());
+        assert_eq!(record.outcome(), Some(Outcome::FiftyMoves));
+    }
+
+    #[test]
+    fn policies() {
+        // white is a human, black a bot
+        let human = |color| color == Color::White;
+        let mut record = record(&["e2e4", "e7e5", "g1f3", "b8c6"]);
+        assert_eq!(record.undo_with(UndoPolicy::WithReply, human), 2);
+        assert_eq!(record.game().side_to_move(), Color::White);
+        assert_eq!(record.redo_with(UndoPolicy::WithReply, human), 2);
+        assert_eq!(record.undo_with(UndoPolicy::Ply, human), 1);
+        assert_eq!(record.game().side_to_move(), Color::Black);
+        // two humans
+        assert_eq!(record.undo_with(UndoPolicy::WithReply, |_| true), 1);
+        assert_eq!(record.ply(), 2);
+    }
+}
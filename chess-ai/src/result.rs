@@ -0,0 +1,73 @@
+// result of a search, returned by Bot::get_move
+use chess::ChessMove;
+use std::fmt;
+use std::time::Duration;
+
+// evaluation of a position from the perspective of the side to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    // material and positional evaluation in centipawns
+    Centipawns(i32),
+    // mate in the given number of moves, negative if the side to move gets mated
+    Mate(i32),
+}
+
+impl Score {
+    // the same score from the other side's perspective
+    pub fn flip(self) -> Score {
+        match self {
+            Score::Centipawns(cp) => Score::Centipawns(-cp),
+            Score::Mate(n) => Score::Mate(-n),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Centipawns(cp) => write!(f, "{:+.2}", *cp as f32 / 100.),
+            Score::Mate(n) if *n < 0 => write!(f, "-#{}", -n),
+            Score::Mate(n) => write!(f, "#{}", n),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    // None if the side to move has no legal moves
+    pub best_move: Option<ChessMove>,
+    pub score: Score,
+    // depth of the last completed iteration
+    pub depth: u8,
+    // nodes of the main search
+    pub nodes: u64,
+    // nodes of the quiescence search
+    pub qnodes: u64,
+    pub time: Duration,
+    // principal variation, starting with the best move
+    pub pv: Vec<ChessMove>,
+}
+
+impl SearchResult {
+    // nodes per second, main and quiescence search combined
+    pub fn nps(&self) -> u64 {
+        let millis = self.time.as_millis() as u64;
+        ((self.nodes + self.qnodes) * 1000)
+            .checked_div(millis)
+            .unwrap_or(0)
+    }
+}
+
+impl fmt::Display for SearchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "depth {} score {} nodes {} ({} quiescence) time {:.2?} pv",
+            self.depth, self.score, self.nodes, self.qnodes, self.time
+        )?;
+        for m in &self.pv {
+            write!(f, " {}", m)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+// standard algebraic notation, the chess crate only parses it
+use chess::{Board, BoardStatus, ChessMove, File, MoveGen, Piece, Square};
+use std::str::FromStr;
+
+fn piece_letter(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn => "",
+        Piece::Knight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook => "R",
+        Piece::Queen => "Q",
+        Piece::King => "K",
+    }
+}
+
+fn file_letter(file: File) -> char {
+    (b'a' + file.to_index() as u8) as char
+}
+
+// SAN of a legal move, with disambiguation, captures, promotions and check or mate
+pub fn to_san(board: &Board, m: ChessMove) -> String {
+    let source = m.get_source();
+    let dest = m.get_dest();
+    let piece = board
+        .piece_on(source)
+        .expect("The moving piece is on the source square");
+
+    let mut san = String::new();
+    let castles = piece == Piece::King
+        && (source.get_file().to_index() as i32 - dest.get_file().to_index() as i32).abs() == 2;
+    if castles {
+        san.push_str(if dest.get_file() == File::G {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        san.push_str(piece_letter(piece));
+        // other pieces of the same kind that can move to the same square
+        let others: Vec<ChessMove> = MoveGen::new_legal(board)
+            .filter(|o| {
+                o.get_dest() == dest
+                    && o.get_source() != source
+                    && board.piece_on(o.get_source()) == Some(piece)
+            })
+            .collect();
+        if piece != Piece::Pawn && !others.is_empty() {
+            let same_file = others
+                .iter()
+                .any(|o| o.get_source().get_file() == source.get_file());
+            let same_rank = others
+                .iter()
+                .any(|o| o.get_source().get_rank() == source.get_rank());
+            if !same_file {
+                san.push(file_letter(source.get_file()));
+            } else if !same_rank {
+                san.push_str(&(source.get_rank().to_index() + 1).to_string());
+            } else {
+                san.push_str(&source.to_string());
+            }
+        }
+        let en_passant = piece == Piece::Pawn && source.get_file() != dest.get_file();
+        if board.piece_on(dest).is_some() || en_passant {
+            if piece == Piece::Pawn {
+                san.push(file_letter(source.get_file()));
+            }
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+        if let Some(promotion) = m.get_promotion() {
+            san.push('=');
+            san.push_str(piece_letter(promotion));
+        }
+    }
+
+    let after = board.make_move_new(m);
+    if after.status() == BoardStatus::Checkmate {
+        san.push('#');
+    } else if after.checkers().popcnt() > 0 {
+        san.push('+');
+    }
+    san
+}
+
+// a line of moves in SAN, e.g. the principal variation of a search. Stops at the first illegal move
+pub fn line_to_san(board: &Board, moves: &[ChessMove]) -> String {
+    let mut board = *board;
+    let mut line = Vec::new();
+    for m in moves {
+        if !board.legal(*m) {
+            break;
+        }
+        line.push(to_san(&board, *m));
+        board = board.make_move_new(*m);
+    }
+    line.join(" ")
+}
+
+// parse a move in SAN, more lenient than ChessMove::from_san: en passant, castling with zeros,
+// superfluous disambiguation and annotations like ! and ? are accepted
+pub fn parse_san(board: &Board, san: &str) -> Result<ChessMove, String> {
+    let s = san
+        .trim()
+        .trim_end_matches("e.p.")
+        .trim_end_matches("(ep)")
+        .trim_end()
+        .trim_end_matches(['+', '#', '!', '?']);
+    let legal = MoveGen::new_legal(board);
+    let candidates: Vec<ChessMove> = match s {
+        "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+            let king = board.king_square(board.side_to_move());
+            let file = if s.len() == 3 { File::G } else { File::C };
+            legal
+                .filter(|m| {
+                    m.get_source() == king
+                        && m.get_dest().get_file() == file
+                        && king.get_file() == File::E
+                })
+                .collect()
+        }
+        _ => {
+            let (piece, rest) = match s.chars().next() {
+                Some('N') => (Piece::Knight, &s[1..]),
+                Some('B') => (Piece::Bishop, &s[1..]),
+                Some('R') => (Piece::Rook, &s[1..]),
+                Some('Q') => (Piece::Queen, &s[1..]),
+                Some('K') => (Piece::King, &s[1..]),
+                _ => (Piece::Pawn, s),
+            };
+            // promotion, with or without =
+            let (rest, promotion) = match rest.char_indices().last() {
+                Some((i, c)) if piece == Piece::Pawn && "NBRQ".contains(c) => {
+                    let promotion = match c {
+                        'N' => Piece::Knight,
+                        'B' => Piece::Bishop,
+                        'R' => Piece::Rook,
+                        _ => Piece::Queen,
+                    };
+                    (rest[..i].trim_end_matches('='), Some(promotion))
+                }
+                _ => (rest, None),
+            };
+            if rest.len() < 2 || !rest.is_char_boundary(rest.len() - 2) {
+                return Err(format!("Invalid move: {}", san));
+            }
+            let (from, to) = rest.split_at(rest.len() - 2);
+            let dest = Square::from_str(to).map_err(|_| format!("Invalid move: {}", san))?;
+            // the rest can only hold the source file and rank, and the capture sign
+            let from: String = from.chars().filter(|c| *c != 'x' && *c != '-').collect();
+            if !from
+                .chars()
+                .all(|c| ('a'..='h').contains(&c) || ('1'..='8').contains(&c))
+            {
+                return Err(format!("Invalid move: {}", san));
+            }
+            legal
+                .filter(|m| {
+                    let source = m.get_source().to_string();
+                    m.get_dest() == dest
+                        && m.get_promotion() == promotion
+                        && board.piece_on(m.get_source()) == Some(piece)
+                        && from.chars().all(|c| source.contains(c))
+                })
+                .collect()
+        }
+    };
+    match candidates.len() {
+        0 => Err(format!("Illegal move: {}", san)),
+        1 => Ok(candidates[0]),
+        _ => Err(format!("Ambiguous move: {}", san)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn san(fen: &str, m: &str) -> String {
+        let board = Board::from_str(fen).unwrap();
+        to_san(&board, ChessMove::from_str(m).unwrap())
+    }
+
+    #[test]
+    fn notation() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+        // castling, captures and en passant
+        let fen = "r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1";
+        assert_eq!(san(fen, "e1g1"), "O-O");
+        assert_eq!(san(fen, "e1c1"), "O-O-O");
+        assert_eq!(san(fen, "e5d6"), "exd6");
+        assert_eq!(san(fen, "a1a8"), "Rxa8+");
+        // disambiguation by file, by rank and by both
+        assert_eq!(san("k7/8/8/8/8/8/8/KN3N2 w - - 0 1", "b1d2"), "Nbd2");
+        assert_eq!(san("7k/8/8/8/R7/8/R7/K7 w - - 0 1", "a2a3"), "R2a3");
+        assert_eq!(san("7k/8/8/8/2Q1Q3/8/2Q5/K7 w - - 0 1", "c4d3"), "Qc4d3");
+        // promotion with mate
+        assert_eq!(san("7k/P6p/8/8/8/8/8/K5R1 w - - 0 1", "a7a8q"), "a8=Q#");
+    }
+
+    #[test]
+    fn parsing() {
+        let parse = |fen: &str, san: &str| {
+            parse_san(&Board::from_str(fen).unwrap(), san).map(|m| m.to_string())
+        };
+        let fen = "r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1";
+        assert_eq!(parse(fen, "exd6 e.p."), Ok("e5d6".to_string()));
+        assert_eq!(parse(fen, "0-0-0"), Ok("e1c1".to_string()));
+        assert_eq!(parse(fen, "Ra1xa8+!"), Ok("a1a8".to_string()));
+        assert_eq!(
+            parse("7k/P7/8/8/8/8/8/K7 w - - 0 1", "a8N"),
+            Ok("a7a8n".to_string())
+        );
+        let knights = "k7/8/8/8/8/8/8/KN3N2 w - - 0 1";
+        assert!(parse(knights, "Nd2").unwrap_err().starts_with("Ambiguous"));
+        assert!(parse(knights, "Nd3").unwrap_err().starts_with("Illegal"));
+        assert!(parse(knights, "Zz9").unwrap_err().starts_with("Invalid"));
+    }
+
+    // every move of the positions reached from a few test positions converts to SAN and back
+    #[test]
+    fn round_trip() {
+        fn visit(board: &Board, depth: u8, count: &mut usize) {
+            for m in MoveGen::new_legal(board) {
+                let san = to_san(board, m);
+                assert_eq!(parse_san(board, &san), Ok(m), "{} in {}", san, board);
+                // the chess crate doesn't understand en passant, and expects promotions without =
+                let en_passant = board.piece_on(m.get_source()) == Some(Piece::Pawn)
+                    && board.piece_on(m.get_dest()).is_none()
+                    && m.get_source().get_file() != m.get_dest().get_file();
+                if !en_passant {
+                    assert_eq!(
+                        ChessMove::from_san(board, &san.replace('=', "")).ok(),
+                        Some(m),
+                        "{} in {}",
+                        san,
+                        board
+                    );
+                }
+                *count += 1;
+                if depth > 1 {
+                    visit(&board.make_move_new(m), depth - 1, count);
+                }
+            }
+        }
+        // positions, depth and number of moves up to that depth, the sums of the perft counts
+        let positions = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                3,
+                9322,
+            ),
+            // kiwipete: castling, pins, en passant
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                2,
+                2087,
+            ),
+            // promotions
+            ("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", 3, 10003),
+        ];
+        for (fen, depth, moves) in positions {
+            let mut count = 0;
+            visit(&Board::from_str(fen).unwrap(), depth, &mut count);
+            assert_eq!(count, moves, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn line() {
+        let board = Board::default();
+        let moves: Vec<ChessMove> = ["e2e4", "e7e5", "g1f3", "a1a8"]
+            .iter()
+            .map(|m| ChessMove::from_str(m).unwrap())
+            .collect();
+        assert_eq!(line_to_san(&board, &moves), "e4 e5 Nf3");
+    }
+}
@@ -0,0 +1,248 @@
+// static exchange evaluation
+// computes the material outcome of a sequence of captures on a single square, without searching.
+// both sides always recapture with their least valuable attacker and can stop capturing whenever that is better for them.
+// pins are ignored.
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, ChessMove, Color, Piece, Square, EMPTY,
+};
+
+use std::cmp;
+
+use crate::PIECE_VALUES;
+
+// material won (positive) or lost (negative) by the side to move when playing the move and
+// letting the exchange on the destination square play out
+// for non-captures this is 0, or negative if the moved piece can be won by the opponent
+pub fn see(board: &Board, mv: ChessMove) -> i32 {
+    let from = mv.get_source();
+    let to = mv.get_dest();
+    let mover = match board.piece_on(from) {
+        Some(p) => p,
+        None => return 0,
+    };
+
+    let mut occupied = *board.combined() ^ BitBoard::from_square(from);
+    let mut captured_value = match board.piece_on(to) {
+        Some(p) => PIECE_VALUES[p.to_index()],
+        None => 0,
+    };
+    // en passant: the captured pawn is not on the destination square
+    if mover == Piece::Pawn && from.get_file() != to.get_file() && board.piece_on(to).is_none() {
+        captured_value = PIECE_VALUES[Piece::Pawn.to_index()];
+        let captured_sq = Square::make_square(from.get_rank(), to.get_file());
+        occupied ^= BitBoard::from_square(captured_sq);
+    }
+    // piece standing on the destination square after the move
+    let mut on_square = mover;
+    if let Some(promotion) = mv.get_promotion() {
+        captured_value += PIECE_VALUES[promotion.to_index()] - PIECE_VALUES[Piece::Pawn.to_index()];
+        on_square = promotion;
+    }
+
+    swap(
+        board,
+        to,
+        captured_value,
+        on_square,
+        occupied,
+        !board.side_to_move(),
+    )
+}
+
+// material the opponent wins by capturing the piece on the given square,
+// 0 if the square is empty or the piece is safe
+pub fn threat_on(board: &Board, square: Square) -> i32 {
+    let (piece, color) = match (board.piece_on(square), board.color_on(square)) {
+        (Some(p), Some(c)) => (p, c),
+        _ => return 0,
+    };
+    let occupied = *board.combined();
+    let attackers = attackers_to(board, square, occupied) & board.color_combined(!color);
+    let (attacker_sq, attacker) = match least_valuable(board, attackers) {
+        Some(a) => a,
+        None => return 0,
+    };
+    if attacker == Piece::King
+        && attackers_to(board, square, occupied) & board.color_combined(color) != EMPTY
+    {
+        return 0;
+    }
+    let occupied = occupied ^ BitBoard::from_square(attacker_sq);
+    // the opponent only captures if it wins material
+    swap(
+        board,
+        square,
+        PIECE_VALUES[piece.to_index()],
+        attacker,
+        occupied,
+        color,
+    )
+    .max(0)
+}
+
+// the actual exchange: `to_move` may recapture `on_square`, the gain so far is `captured_value`
+fn swap(
+    board: &Board,
+    to: Square,
+    captured_value: i32,
+    on_square: Piece,
+    occupied: BitBoard,
+    to_move: Color,
+) -> i32 {
+    let mut gain = [0; 32];
+    let mut d = 0;
+    gain[0] = captured_value;
+
+    let mut occupied = occupied;
+    let mut on_square = on_square;
+    let mut side = to_move;
+    loop {
+        // attackers are recomputed after every capture so x-rays through the captured piece are found
+        let attackers = attackers_to(board, to, occupied) & occupied;
+        let (sq, piece) = match least_valuable(board, attackers & board.color_combined(side)) {
+            Some(a) => a,
+            None => break,
+        };
+        // the king can't capture into a defended square
+        if piece == Piece::King && attackers & board.color_combined(!side) != EMPTY {
+            break;
+        }
+
+        d += 1;
+        gain[d] = PIECE_VALUES[on_square.to_index()] - gain[d - 1];
+        // the previous capture already wins material and this one loses material even if it isn't answered,
+        // the result doesn't depend on the rest of the exchange
+        if cmp::max(-gain[d - 1], gain[d]) < 0 {
+            d -= 1;
+            break;
+        }
+        if d == gain.len() - 1 {
+            break;
+        }
+        occupied ^= BitBoard::from_square(sq);
+        on_square = piece;
+        side = !side;
+    }
+
+    // each side chooses whether to stop or continue the exchange
+    while d > 0 {
+        gain[d - 1] = -cmp::max(-gain[d - 1], gain[d]);
+        d -= 1;
+    }
+    gain[0]
+}
+
+// all pieces of both colors attacking a square, given the occupied squares
+fn attackers_to(board: &Board, sq: Square, occupied: BitBoard) -> BitBoard {
+    let pawns = board.pieces(Piece::Pawn);
+    let bishops_queens = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let rooks_queens = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    // a white pawn attacks the square if a black pawn on the square would attack it, and vice versa
+    (get_pawn_attacks(
+        sq,
+        Color::Black,
+        *pawns & board.color_combined(Color::White),
+    )) | get_pawn_attacks(
+        sq,
+        Color::White,
+        *pawns & board.color_combined(Color::Black),
+    ) | (get_knight_moves(sq) & board.pieces(Piece::Knight))
+        | (get_king_moves(sq) & board.pieces(Piece::King))
+        | (get_bishop_moves(sq, occupied) & bishops_queens)
+        | (get_rook_moves(sq, occupied) & rooks_queens)
+}
+
+fn least_valuable(board: &Board, attackers: BitBoard) -> Option<(Square, Piece)> {
+    [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+        Piece::King,
+    ]
+    .iter()
+    .find_map(|p| {
+        let bb = attackers & board.pieces(*p);
+        if bb == EMPTY {
+            None
+        } else {
+            Some((bb.to_square(), *p))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn see_san(fen: &str, san: &str) -> i32 {
+        let board = Board::from_str(fen).expect("Valid FEN");
+        let m = ChessMove::from_san(&board, san).expect("Valid move");
+        see(&board, m)
+    }
+
+    #[test]
+    fn undefended_pawn() {
+        assert_eq!(
+            see_san("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "Rxe5"),
+            100
+        );
+    }
+
+    #[test]
+    fn defended_pawn_with_xrays() {
+        // Nxe5 Nxe5 Rxe5 Bxe5 Bxe5 Qxe5 Qxe5 Rxe5, white is a knight for a pawn down
+        assert_eq!(
+            see_san(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "Nxe5"
+            ),
+            100 - 300
+        );
+    }
+
+    #[test]
+    fn queen_takes_defended_pawn() {
+        assert_eq!(
+            see_san("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", "Qxd5"),
+            100 - 900
+        );
+    }
+
+    #[test]
+    fn equal_trade() {
+        assert_eq!(see_san("4k3/8/1n6/3n4/8/4N3/8/4K3 w - - 0 1", "Nxd5"), 0);
+    }
+
+    #[test]
+    fn king_cannot_recapture_defended_piece() {
+        // Rxd7 Kxd7 is not possible because of the rook on d1
+        assert_eq!(see_san("4k3/3n4/8/8/8/8/3R4/3RK3 w - - 0 1", "Rxd7"), 300);
+    }
+
+    #[test]
+    fn en_passant() {
+        let board = Board::from_str("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").expect("Valid FEN");
+        let m = ChessMove::new(Square::E5, Square::D6, None);
+        assert!(board.legal(m));
+        assert_eq!(see(&board, m), 100);
+    }
+
+    #[test]
+    fn quiet_move_into_attack() {
+        assert_eq!(see_san("8/4k3/4p3/8/4N3/8/8/4K3 w - - 0 1", "Nc3"), 0);
+        assert_eq!(see_san("8/4k3/4p3/8/4N3/8/8/4K3 w - - 0 1", "Nd6"), -300);
+    }
+
+    #[test]
+    fn hanging_piece() {
+        let board = Board::from_str("4k3/8/4p3/3N1b2/8/8/8/4K3 w - - 0 1").expect("Valid FEN");
+        assert_eq!(threat_on(&board, Square::D5), 300);
+        // the bishop is attacked by nothing
+        assert_eq!(threat_on(&board, Square::F5), 0);
+    }
+}
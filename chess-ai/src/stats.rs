@@ -0,0 +1,521 @@
+// results of a match between two players, counted for the first player, and the sequential
+// probability ratio test (SPRT) to stop a match once it's clear which player is stronger
+use chess::Color;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::outcome::Outcome;
+
+// z for a 95% confidence interval
+const Z_95: f64 = 1.96;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+// elo difference of a player that scores `score` points per game, 0 to 1
+pub fn elo_difference(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+// points per game of a player that is `elo` stronger
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// half points of the first player in a game: 0, 1 for a draw or 2
+pub fn half_points(outcome: Outcome, first: Color) -> u32 {
+    match outcome.winner() {
+        Some(winner) if winner == first => 2,
+        Some(_) => 0,
+        None => 1,
+    }
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // count a finished game, `first` is the color the first player had
+    pub fn add(&mut self, outcome: Outcome, first: Color) {
+        match half_points(outcome, first) {
+            2 => self.wins += 1,
+            1 => self.draws += 1,
+            _ => self.losses += 1,
+        }
+    }
+
+    // points per game, a draw is half a point
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // elo difference and the margin of its 95% confidence interval,
+    // infinite when a player scored every point
+    pub fn elo(&self) -> (f64, f64) {
+        let games = self.games() as f64;
+        let score = self.score();
+        if games == 0.0 {
+            return (0.0, f64::INFINITY);
+        }
+        if score == 0.0 || score == 1.0 {
+            return (elo_difference(score), f64::INFINITY);
+        }
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games;
+        let error = Z_95 * (variance / games).sqrt();
+        let low = elo_difference((score - error).max(0.0));
+        let high = elo_difference((score + error).min(1.0));
+        (elo_difference(score), (high - low) / 2.0)
+    }
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (elo, margin) = self.elo();
+        write!(
+            f,
+            "W/D/L: {}/{}/{}, score {:.1}%, Elo difference: {:+.1} +/- {:.1}",
+            self.wins,
+            self.draws,
+            self.losses,
+            self.score() * 100.0,
+            elo,
+            margin
+        )
+    }
+}
+
+// results of game pairs, the games of a pair start from the same opening with the colors swapped.
+// counts[n] is the number of pairs the first player scored n half points in
+#[derive(Clone, Debug, Default)]
+pub struct PairScore {
+    pub counts: [u32; 5],
+    // half points of the pairs with one finished game
+    unfinished: HashMap<usize, u32>,
+}
+
+impl PairScore {
+    pub fn pairs(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    // count a game of the pair, once both games are finished
+    pub fn add(&mut self, pair: usize, half_points: u32) {
+        match self.unfinished.remove(&pair) {
+            Some(other) => self.counts[(half_points + other) as usize] += 1,
+            None => {
+                self.unfinished.insert(pair, half_points);
+            }
+        }
+    }
+}
+
+// statistics the SPRT looks at: single games (wins, draws, losses), or pairs of games, which
+// accounts for unbalanced openings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtModel {
+    Trinomial,
+    Pentanomial,
+}
+
+// tests the hypothesis that the first player is elo1 stronger against the hypothesis that it's elo0
+// stronger, accepting the wrong one with the error rates alpha (for elo0) and beta (for elo1)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub model: SprtModel,
+}
+
+// the hypothesis accepted by the SPRT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtResult {
+    // the first player isn't stronger by more than elo0
+    H0,
+    // the first player is stronger by at least elo1
+    H1,
+}
+
+impl Default for Sprt {
+    fn default() -> Sprt {
+        Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+            model: SprtModel::Pentanomial,
+        }
+    }
+}
+
+// elo0=<elo>,elo1=<elo>[,alpha=<p>,beta=<p>,model=trinomial|pentanomial], missing keys keep the defaults
+impl FromStr for Sprt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sprt, String> {
+        let mut sprt = Sprt::default();
+        for param in s.split(',').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let number = || {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.is_finite())
+                    .ok_or_else(|| format!("Invalid value for {}: '{}'", key, value))
+            };
+            match key {
+                "elo0" => sprt.elo0 = number()?,
+                "elo1" => sprt.elo1 = number()?,
+                "alpha" => sprt.alpha = number()?,
+                "beta" => sprt.beta = number()?,
+                "model" => {
+                    sprt.model = match value {
+                        "trinomial" => SprtModel::Trinomial,
+                        "pentanomial" => SprtModel::Pentanomial,
+                        _ => return Err(format!("Invalid SPRT model: '{}'", value)),
+                    }
+                }
+                _ => return Err(format!("Unknown SPRT option: {}", key)),
+            }
+        }
+        if sprt.elo0 >= sprt.elo1 {
+            return Err("elo0 has to be smaller than elo1".to_string());
+        }
+        let probability = |p: f64| p > 0.0 && p < 0.5;
+        if !probability(sprt.alpha) || !probability(sprt.beta) {
+            return Err("alpha and beta have to be between 0 and 0.5".to_string());
+        }
+        Ok(sprt)
+    }
+}
+
+impl Sprt {
+    // the test stops when the log-likelihood ratio leaves these bounds
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // log-likelihood ratio of the hypotheses with the statistics of the model
+    pub fn llr(&self, score: &MatchScore, pairs: &PairScore) -> f64 {
+        match self.model {
+            SprtModel::Trinomial => {
+                self.llr_of(&[(0.0, score.losses), (0.5, score.draws), (1.0, score.wins)])
+            }
+            SprtModel::Pentanomial => {
+                let results: Vec<(f64, u32)> =
+                    (0..5).map(|n| (n as f64 / 4.0, pairs.counts[n])).collect();
+                self.llr_of(&results)
+            }
+        }
+    }
+
+    // normal approximation of the generalized SPRT for samples with these scores, 0 to 1, and counts.
+    // half a loss and half a win are added as a prior, the variance of one-sided results like only
+    // wins and draws would be zero, and a few games would decide the test
+    fn llr_of(&self, results: &[(f64, u32)]) -> f64 {
+        let mut results: Vec<(f64, f64)> = results
+            .iter()
+            .map(|(s, count)| (*s, *count as f64))
+            .collect();
+        results.extend([(0.0, 0.5), (1.0, 0.5)]);
+        let n: f64 = results.iter().map(|(_, count)| count).sum();
+        let mean = results.iter().map(|(s, count)| s * count).sum::<f64>() / n;
+        let variance = results
+            .iter()
+            .map(|(s, count)| (s - mean).powi(2) * count)
+            .sum::<f64>()
+            / n;
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        n * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    // the accepted hypothesis, None while the test goes on
+    pub fn result(&self, llr: f64) -> Option<SprtResult> {
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(SprtResult::H1)
+        } else if llr <= lower {
+            Some(SprtResult::H0)
+        } else {
+            None
+        }
+    }
+}
+
+// summary of a match for scripts, see to_json
+pub struct MatchReport<'a> {
+    pub players: [&'a str; 2],
+    pub score: MatchScore,
+    pub pairs: &'a PairScore,
+    pub sprt: Option<Sprt>,
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// null for infinite elo differences and margins
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{:.2}", x)
+    } else {
+        "null".to_string()
+    }
+}
+
+impl MatchReport<'_> {
+    // a JSON object with the players, the results, the elo difference and the SPRT with its result,
+    // "H0", "H1" or null if the test didn't decide
+    pub fn to_json(&self) -> String {
+        let score = &self.score;
+        let (elo, margin) = score.elo();
+        let mut fields = vec![
+            format!(
+                "\"players\": [{}, {}]",
+                json_string(self.players[0]),
+                json_string(self.players[1])
+            ),
+            format!("\"games\": {}", score.games()),
+            format!("\"wins\": {}", score.wins),
+            format!("\"draws\": {}", score.draws),
+            format!("\"losses\": {}", score.losses),
+            format!("\"pentanomial\": {:?}", self.pairs.counts),
+            format!("\"score\": {}", json_number(score.score())),
+            format!("\"elo\": {}", json_number(elo)),
+            format!("\"elo_margin\": {}", json_number(margin)),
+        ];
+        if let Some(sprt) = &self.sprt {
+            let llr = sprt.llr(score, self.pairs);
+            let (lower, upper) = sprt.bounds();
+            let result = match sprt.result(llr) {
+                Some(SprtResult::H0) => "\"H0\"",
+                Some(SprtResult::H1) => "\"H1\"",
+                None => "null",
+            };
+            let model = match sprt.model {
+                SprtModel::Trinomial => "trinomial",
+                SprtModel::Pentanomial => "pentanomial",
+            };
+            fields.push(format!(
+                "\"sprt\": {{\"elo0\": {}, \"elo1\": {}, \"alpha\": {}, \"beta\": {}, \"model\": \"{}\", \"llr\": {}, \"lower_bound\": {}, \"upper_bound\": {}, \"result\": {}}}",
+                sprt.elo0,
+                sprt.elo1,
+                sprt.alpha,
+                sprt.beta,
+                model,
+                json_number(llr),
+                json_number(lower),
+                json_number(upper),
+                result
+            ));
+        }
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 0.1
+    }
+
+    #[test]
+    fn elo() {
+        assert_eq!(elo_difference(0.5), 0.0);
+        assert!(close(elo_difference(0.75), 190.8));
+        assert!(close(elo_difference(0.25), -190.8));
+
+        let mut score = MatchScore::default();
+        score.add(Outcome::Checkmate(Color::Black), Color::White);
+        score.add(Outcome::Timeout(Color::White), Color::Black);
+        score.add(Outcome::Resignation(Color::White), Color::White);
+        score.add(Outcome::Stalemate, Color::Black);
+        assert_eq!(
+            score,
+            MatchScore {
+                wins: 2,
+                draws: 1,
+                losses: 1
+            }
+        );
+        assert_eq!(score.score(), 0.625);
+        let (elo, margin) = score.elo();
+        assert!(close(elo, 88.7));
+        assert!(margin > 100.0);
+
+        // more games, smaller margin
+        let many = MatchScore {
+            wins: 200,
+            draws: 100,
+            losses: 100,
+        };
+        let (elo, small_margin) = many.elo();
+        assert!(close(elo, 88.7));
+        assert!(small_margin < margin / 5.0);
+        assert_eq!(
+            many.to_string(),
+            format!(
+                "W/D/L: 200/100/100, score 62.5%, Elo difference: +88.7 +/- {:.1}",
+                small_margin
+            )
+        );
+
+        let all_wins = MatchScore {
+            wins: 3,
+            ..MatchScore::default()
+        };
+        assert_eq!(all_wins.elo(), (f64::INFINITY, f64::INFINITY));
+        let draw = MatchScore {
+            draws: 1,
+            ..MatchScore::default()
+        };
+        assert_eq!(
+            draw.to_string(),
+            "W/D/L: 0/1/0, score 50.0%, Elo difference: +0.0 +/- 0.0"
+        );
+    }
+
+    #[test]
+    fn pairs() {
+        let mut pairs = PairScore::default();
+        pairs.add(0, 2);
+        pairs.add(1, 1);
+        assert_eq!(pairs.pairs(), 0);
+        pairs.add(0, 1);
+        pairs.add(2, 0);
+        pairs.add(2, 0);
+        assert_eq!(pairs.counts, [1, 0, 0, 1, 0]);
+        assert_eq!(pairs.pairs(), 2);
+    }
+
+    #[test]
+    fn sprt() {
+        let sprt: Sprt = "elo0=0,elo1=10,alpha=0.05,beta=0.1,model=trinomial"
+            .parse()
+            .unwrap();
+        assert_eq!(sprt.elo1, 10.0);
+        assert_eq!(sprt.beta, 0.1);
+        assert_eq!(sprt.model, SprtModel::Trinomial);
+        let (lower, upper) = sprt.bounds();
+        assert!(close(lower, -2.25) && close(upper, 2.89));
+        for s in [
+            "elo0=5,elo1=0",
+            "alpha=0.7",
+            "beta=0",
+            "model=binomial",
+            "elo=5",
+        ] {
+            assert!(s.parse::<Sprt>().is_err(), "{}", s);
+        }
+
+        // a player that scores 60% is stronger, one that scores 45% isn't
+        let winning = MatchScore {
+            wins: 400,
+            draws: 400,
+            losses: 200,
+        };
+        let llr = sprt.llr(&winning, &PairScore::default());
+        assert!(llr > upper);
+        assert_eq!(sprt.result(llr), Some(SprtResult::H1));
+        let losing = MatchScore {
+            wins: 300,
+            draws: 300,
+            losses: 400,
+        };
+        assert_eq!(
+            sprt.result(sprt.llr(&losing, &PairScore::default())),
+            Some(SprtResult::H0)
+        );
+        let even = MatchScore {
+            wins: 10,
+            draws: 10,
+            losses: 10,
+        };
+        assert_eq!(sprt.result(sprt.llr(&even, &PairScore::default())), None);
+        // one-sided results decide only after enough games
+        let wins = |wins| MatchScore {
+            wins,
+            ..MatchScore::default()
+        };
+        assert_eq!(sprt.result(sprt.llr(&wins(3), &PairScore::default())), None);
+        assert_eq!(
+            sprt.result(sprt.llr(&wins(30), &PairScore::default())),
+            Some(SprtResult::H1)
+        );
+        let draws = MatchScore {
+            draws: 1000,
+            ..MatchScore::default()
+        };
+        assert_eq!(
+            sprt.result(sprt.llr(&draws, &PairScore::default())),
+            Some(SprtResult::H0)
+        );
+
+        // pairs keep the information of the openings
+        let sprt = Sprt {
+            model: SprtModel::Pentanomial,
+            ..sprt
+        };
+        let pairs = PairScore {
+            counts: [20, 100, 200, 150, 30],
+            ..PairScore::default()
+        };
+        let llr = sprt.llr(&MatchScore::default(), &pairs);
+        assert!(llr > upper, "{}", llr);
+        assert!(sprt.llr(&winning, &PairScore::default()) < 0.0);
+    }
+
+    #[test]
+    fn report() {
+        let pairs = PairScore {
+            counts: [0, 0, 1, 0, 0],
+            ..PairScore::default()
+        };
+        let mut report = MatchReport {
+            players: ["bot:depth=4", "engine \"x\""],
+            score: MatchScore {
+                wins: 1,
+                draws: 0,
+                losses: 1,
+            },
+            pairs: &pairs,
+            sprt: None,
+        };
+        let json = report.to_json();
+        assert!(json.starts_with(
+            r#"{"players": ["bot:depth=4", "engine \"x\""], "games": 2, "wins": 1, "draws": 0, "losses": 1, "pentanomial": [0, 0, 1, 0, 0], "score": 0.50, "elo": 0.00, "elo_margin": "#
+        ));
+        assert!(json.ends_with('}'));
+        report.sprt = Some(Sprt::default());
+        let json = report.to_json();
+        assert!(json.contains(
+            r#""sprt": {"elo0": 0, "elo1": 5, "alpha": 0.05, "beta": 0.05, "model": "pentanomial", "llr": "#
+        ));
+        assert!(json.ends_with(r#""lower_bound": -2.94, "upper_bound": 2.94, "result": null}}"#));
+    }
+}
@@ -0,0 +1,197 @@
+// transposition table
+// stores the results of already searched positions, indexed by the zobrist hash of the board
+use chess::{ChessMove, ALL_PIECES, ALL_SQUARES};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+
+// how the stored score relates to the real score of the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    // the score is exact
+    Exact,
+    // the search failed high, the real score is at least the stored score
+    Lower,
+    // the search failed low, the real score is at most the stored score
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TTEntry {
+    pub key: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<ChessMove>,
+}
+
+// the table can be shared between search threads without locking.
+// every slot holds the entry packed into one word and the key xor-ed with that word,
+// so a slot torn by two threads writing at the same time doesn't match any key and is ignored
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// layout of the packed entry
+const SCORE_BITS: u64 = 0xffff_ffff;
+const DEPTH_SHIFT: u64 = 32;
+const BOUND_SHIFT: u64 = 40;
+const MOVE_SHIFT: u64 = 42;
+// distinguishes stored entries from empty slots
+const VALID: u64 = 1 << 63;
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let num_entries = (size_mb * 1024 * 1024 / mem::size_of::<Slot>()).max(1);
+        TranspositionTable {
+            slots: (0..num_entries).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.slots.len() as u64) as usize
+    }
+
+    // look up the entry for a position, if it has been stored
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        let slot = &self.slots[self.index(key)];
+        let data = slot.data.load(Ordering::Relaxed);
+        if data & VALID == 0 || slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        Some(unpack(key, data))
+    }
+
+    // store the result of a search
+    // an entry for the same position is only overwritten by a search of at least the same depth,
+    // entries of other positions are always replaced
+    pub fn store(
+        &self,
+        key: u64,
+        depth: u8,
+        bound: Bound,
+        score: i32,
+        best_move: Option<ChessMove>,
+    ) {
+        if let Some(old) = self.probe(key) {
+            if old.depth > depth {
+                return;
+            }
+        }
+        let data = pack(depth, bound, score, best_move);
+        let slot = &self.slots[self.index(key)];
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // number of entries the table can hold
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+fn pack(depth: u8, bound: Bound, score: i32, best_move: Option<ChessMove>) -> u64 {
+    let bound = match bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    // 0 is no move, otherwise 1 bit set, 6 bits source, 6 bits destination, 3 bits promotion
+    let m = match best_move {
+        Some(m) => {
+            let promotion = m.get_promotion().map_or(0, |p| p.to_index() as u64 + 1);
+            1 << 15
+                | (m.get_source().to_index() as u64) << 9
+                | (m.get_dest().to_index() as u64) << 3
+                | promotion
+        }
+        None => 0,
+    };
+    VALID
+        | (score as u32 as u64)
+        | (depth as u64) << DEPTH_SHIFT
+        | bound << BOUND_SHIFT
+        | m << MOVE_SHIFT
+}
+
+fn unpack(key: u64, data: u64) -> TTEntry {
+    let bound = match (data >> BOUND_SHIFT) & 3 {
+        0 => Bound::Exact,
+        1 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    let m = (data >> MOVE_SHIFT) & 0xffff;
+    let best_move = if m == 0 {
+        None
+    } else {
+        let promotion = match m & 7 {
+            0 => None,
+            p => Some(ALL_PIECES[p as usize - 1]),
+        };
+        Some(ChessMove::new(
+            ALL_SQUARES[((m >> 9) & 63) as usize],
+            ALL_SQUARES[((m >> 3) & 63) as usize],
+            promotion,
+        ))
+    };
+    TTEntry {
+        key,
+        depth: (data >> DEPTH_SHIFT) as u8,
+        bound,
+        score: (data & SCORE_BITS) as u32 as i32,
+        best_move,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Piece, Square};
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let m = ChessMove::new(Square::E2, Square::E4, None);
+        tt.store(42, 3, Bound::Exact, 17, Some(m));
+        let entry = tt.probe(42).expect("entry was stored");
+        assert_eq!(entry.depth, 3);
+        assert_eq!(entry.bound, Bound::Exact);
+        assert_eq!(entry.score, 17);
+        assert_eq!(entry.best_move, Some(m));
+        // same slot, different position
+        assert!(tt.probe(42 + tt.capacity() as u64).is_none());
+
+        // shallower results don't overwrite deeper ones
+        tt.store(42, 2, Bound::Lower, 5, None);
+        assert_eq!(tt.probe(42).unwrap().score, 17);
+
+        tt.clear();
+        assert!(tt.probe(42).is_none());
+    }
+
+    #[test]
+    fn packing() {
+        let tt = TranspositionTable::new(1);
+        let m = ChessMove::new(Square::H7, Square::G8, Some(Piece::Knight));
+        tt.store(7, 255, Bound::Upper, -123456, Some(m));
+        let entry = tt.probe(7).expect("entry was stored");
+        assert_eq!(entry.depth, 255);
+        assert_eq!(entry.bound, Bound::Upper);
+        assert_eq!(entry.score, -123456);
+        assert_eq!(entry.best_move, Some(m));
+    }
+}
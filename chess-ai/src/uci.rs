@@ -0,0 +1,365 @@
+// UCI (universal chess interface) front end, lets chess GUIs and tournament managers use the bot as an engine
+// commands are read line by line from the input, responses are written to the output
+use chess::Color;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::engine::{send, Engine, Output, Position, SearchDone, SearchLimits};
+use crate::tt::DEFAULT_HASH_SIZE_MB;
+use crate::{Bot, Score, SearchLog, SearchResult};
+
+const ENGINE_NAME: &str = "chess-ai";
+const DEFAULT_DEPTH: u8 = 64;
+
+struct Uci<W: Write + Send + 'static> {
+    out: Output<W>,
+    position: Position,
+    engine: Engine,
+    // depth used when `go` doesn't specify one
+    depth: u8,
+}
+
+// run the UCI loop until `quit` or the end of the input
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) {
+    let mut uci = Uci {
+        out: Arc::new(Mutex::new(output)),
+        position: Position::default(),
+        engine: Engine::new(Bot::new(Color::White, DEFAULT_DEPTH)),
+        depth: DEFAULT_DEPTH,
+    };
+    for line in input.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if !uci.handle(&line) {
+            return;
+        }
+    }
+    // end of input: let a running search finish, unless it would never end on its own
+    let infinite = uci.engine.is_infinite();
+    uci.engine.finish(infinite);
+}
+
+// info line for a completed iteration
+pub fn info_line(result: &SearchResult) -> String {
+    let score = match result.score {
+        Score::Centipawns(cp) => format!("cp {}", cp),
+        Score::Mate(n) => format!("mate {}", n),
+    };
+    let mut line = format!(
+        "info depth {} score {} nodes {} nps {} time {}",
+        result.depth,
+        score,
+        result.nodes + result.qnodes,
+        result.nps(),
+        result.time.as_millis()
+    );
+    if !result.pv.is_empty() {
+        line.push_str(" pv");
+        for m in &result.pv {
+            line.push_str(&format!(" {}", m));
+        }
+    }
+    line
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    // returns false when the engine should quit
+    fn handle(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                self.send(&format!("id name {}", ENGINE_NAME));
+                self.send("id author chess-ai authors");
+                self.send(&format!(
+                    "option name Hash type spin default {} min 1 max 4096",
+                    DEFAULT_HASH_SIZE_MB
+                ));
+                self.send("option name Threads type spin default 1 min 1 max 256");
+                self.send(&format!(
+                    "option name Depth type spin default {} min 1 max 255",
+                    DEFAULT_DEPTH
+                ));
+                self.send("uciok");
+            }
+            Some("isready") => self.send("readyok"),
+            Some("ucinewgame") => {
+                self.engine.bot().new_game();
+                self.position = Position::default();
+            }
+            Some("position") => {
+                let args: Vec<&str> = tokens.collect();
+                match parse_position(&args) {
+                    Ok(position) => self.position = position,
+                    Err(e) => self.send(&format!("info string {}", e)),
+                }
+            }
+            Some("go") => {
+                let args: Vec<&str> = tokens.collect();
+                let limits = parse_go(&args, self.position.board().side_to_move());
+                self.go(limits);
+            }
+            Some("stop") => {
+                self.engine.finish(true);
+            }
+            Some("setoption") => {
+                let args: Vec<&str> = tokens.collect();
+                self.set_option(&args);
+            }
+            Some("quit") => {
+                self.engine.finish(true);
+                return false;
+            }
+            Some(command) => self.send(&format!("info string Unknown command: {}", command)),
+            None => {}
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    fn go(&mut self, limits: SearchLimits) {
+        let out = Arc::clone(&self.out);
+        let log: SearchLog = Box::new(move |result: &SearchResult| send(&out, &info_line(result)));
+        let out = Arc::clone(&self.out);
+        let done: SearchDone = Box::new(move |result: &SearchResult| {
+            let best_move = result
+                .best_move
+                .map_or("0000".to_string(), |m| m.to_string());
+            send(&out, &format!("bestmove {}", best_move));
+        });
+        self.engine
+            .start(&self.position, limits, self.depth, Some(log), done);
+    }
+
+    // setoption name <name> value <value>
+    fn set_option(&mut self, args: &[&str]) {
+        let value_idx = args.iter().position(|a| *a == "value");
+        let name = args[..value_idx.unwrap_or(args.len())]
+            .iter()
+            .skip_while(|a| **a == "name")
+            .cloned()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let value = value_idx
+            .and_then(|i| args.get(i + 1))
+            .and_then(|v| v.parse::<usize>().ok());
+        let value = match value {
+            Some(v) if v > 0 => v,
+            _ => {
+                self.send(&format!("info string Invalid value for option {}", name));
+                return;
+            }
+        };
+        match name.to_lowercase().as_str() {
+            "hash" => self.engine.bot().set_hash_size(value),
+            "threads" => self.engine.bot().set_threads(value),
+            "depth" => self.depth = value.min(u8::MAX as usize) as u8,
+            _ => self.send(&format!("info string Unknown option: {}", name)),
+        }
+    }
+}
+
+// position startpos|fen <fen> [moves <move>...]
+fn parse_position(args: &[&str]) -> Result<Position, String> {
+    let moves_idx = args
+        .iter()
+        .position(|a| *a == "moves")
+        .unwrap_or(args.len());
+    let mut position = match args.first() {
+        Some(&"startpos") => Position::default(),
+        Some(&"fen") => Position::from_fen(&args[1..moves_idx].join(" "))?,
+        _ => return Err("Expected startpos or fen".to_string()),
+    };
+    for s in args.iter().skip(moves_idx + 1) {
+        let m = position.parse_move(s)?;
+        position.make_move(m)?;
+    }
+    Ok(position)
+}
+
+// the moves after searchmoves run until the next of these
+const GO_OPTIONS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "infinite",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+];
+
+// go [depth <n>] [nodes <n>] [movetime <ms>] [wtime <ms>] [btime <ms>] [winc <ms>] [binc <ms>] [movestogo <n>] [infinite]
+// ponder and searchmoves are accepted but ignored
+fn parse_go(args: &[&str], side_to_move: Color) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).and_then(|v| v.parse::<u64>().ok());
+        let millis = value.map(Duration::from_millis);
+        let (time, inc) = match side_to_move {
+            Color::White => ("wtime", "winc"),
+            Color::Black => ("btime", "binc"),
+        };
+        match args[i] {
+            "infinite" => {
+                limits.infinite = true;
+                i += 1;
+                continue;
+            }
+            "ponder" => {
+                i += 1;
+                continue;
+            }
+            "searchmoves" => {
+                i += 1;
+                while i < args.len() && !GO_OPTIONS.contains(&args[i]) {
+                    i += 1;
+                }
+                continue;
+            }
+            "depth" => limits.depth = value.map(|d| d.clamp(1, u8::MAX as u64) as u8),
+            "nodes" => limits.nodes = value,
+            "movetime" => limits.move_time = millis,
+            "movestogo" => limits.moves_to_go = value.map(|m| m as u32),
+            a if a == time => limits.time_left = millis,
+            a if a == inc => limits.increment = millis.unwrap_or_default(),
+            // the opponent's clock and unsupported options
+            _ => {}
+        }
+        i += 2;
+    }
+    limits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // collects the output of the engine
+    #[derive(Clone, Default)]
+    struct Transcript(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Transcript {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_script(script: &str) -> Vec<String> {
+        let transcript = Transcript::default();
+        run(script.as_bytes(), transcript.clone());
+        let output = transcript.0.lock().unwrap().clone();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn handshake() {
+        let output = run_script("uci\nisready\nquit\n");
+        assert!(output[0].starts_with("id name"));
+        assert!(output.iter().any(|l| l.starts_with("option name Hash")));
+        assert!(output.iter().any(|l| l.starts_with("option name Threads")));
+        assert_eq!(output[output.len() - 2], "uciok");
+        assert_eq!(output[output.len() - 1], "readyok");
+    }
+
+    #[test]
+    fn go_depth() {
+        let output = run_script(
+            "uci\nsetoption name Hash value 1\nsetoption name Threads value 1\nucinewgame\n\
+             position startpos moves e2e4 e7e5\ngo depth 3\n",
+        );
+        let info: Vec<&String> = output
+            .iter()
+            .filter(|l| l.starts_with("info depth"))
+            .collect();
+        assert_eq!(info.len(), 3);
+        assert!(info[2].starts_with("info depth 3 score cp "));
+        assert!(
+            info[2].contains(" nodes ") && info[2].contains(" nps ") && info[2].contains(" pv ")
+        );
+        let best = output.last().unwrap();
+        assert!(best.starts_with("bestmove "));
+        // the best move is the first move of the principal variation
+        let m = best.split_whitespace().nth(1).unwrap();
+        assert!(info[2].contains(&format!(" pv {}", m)));
+    }
+
+    #[test]
+    fn mate_score_from_fen() {
+        let output = run_script("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo depth 2\n");
+        assert!(output.iter().any(|l| l.contains("score mate 1")));
+        assert_eq!(output.last().unwrap(), "bestmove a1a8");
+    }
+
+    #[test]
+    fn infinite_until_stop() {
+        let output = run_script("position startpos\ngo infinite\nstop\nquit\n");
+        assert_eq!(
+            output.iter().filter(|l| l.starts_with("bestmove")).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn time_controls() {
+        let limits = parse_go(
+            &[
+                "wtime", "60000", "btime", "1000", "winc", "1000", "binc", "0",
+            ],
+            Color::Black,
+        );
+        assert_eq!(limits.time_left, Some(Duration::from_millis(1000)));
+        assert_eq!(limits.increment, Duration::ZERO);
+        let limits = parse_go(&["movetime", "500", "depth", "7"], Color::White);
+        assert_eq!(limits.move_time, Some(Duration::from_millis(500)));
+        assert_eq!(limits.depth, Some(7));
+
+        // flags and move lists between the values
+        let limits = parse_go(
+            &["ponder", "wtime", "60000", "btime", "60000"],
+            Color::White,
+        );
+        assert_eq!(limits.time_left, Some(Duration::from_millis(60000)));
+        let limits = parse_go(
+            &[
+                "searchmoves",
+                "e2e4",
+                "d2d4",
+                "wtime",
+                "60000",
+                "winc",
+                "500",
+            ],
+            Color::White,
+        );
+        assert_eq!(limits.time_left, Some(Duration::from_millis(60000)));
+        assert_eq!(limits.increment, Duration::from_millis(500));
+        let limits = parse_go(&["searchmoves", "e2e4", "infinite"], Color::White);
+        assert!(limits.infinite);
+    }
+
+    #[test]
+    fn illegal_position() {
+        let output = run_script("position startpos moves e2e5\nposition fen nonsense\n");
+        assert_eq!(output.len(), 2);
+        assert!(output.iter().all(|l| l.starts_with("info string")));
+    }
+}
@@ -0,0 +1,408 @@
+// XBoard / CECP (chess engine communication protocol) front end, for XBoard and older tools
+// commands are read line by line from the input, responses are written to the output
+use chess::{BoardStatus, Color};
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::engine::{send, Engine, Output, Position, SearchDone, TimeControl};
+use crate::{Bot, Score, SearchLog, SearchResult};
+
+const ENGINE_NAME: &str = "chess-ai";
+const DEFAULT_DEPTH: u8 = 64;
+// xboard reports mate scores as large centipawn values
+const MATE_SCORE: i32 = 100000;
+
+struct XBoard<W: Write + Send + 'static> {
+    out: Output<W>,
+    position: Position,
+    engine: Engine,
+    // the side the engine plays, None in force mode
+    engine_side: Option<Color>,
+    time_control: TimeControl,
+    // time left on the engine's clock, as reported by the `time` command
+    time_left: Option<Duration>,
+    // send thinking output
+    post: bool,
+    // state of the running search, shared with its threads
+    search_state: Arc<SearchState>,
+}
+
+#[derive(Default)]
+struct SearchState {
+    // the search was aborted, its move must not be played
+    cancelled: AtomicBool,
+    // the move has been sent to the GUI
+    moved: AtomicBool,
+}
+
+// run the XBoard loop until `quit` or the end of the input
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) {
+    let mut xboard = XBoard {
+        out: Arc::new(Mutex::new(output)),
+        position: Position::default(),
+        engine: Engine::new(Bot::new(Color::White, DEFAULT_DEPTH)),
+        engine_side: Some(Color::Black),
+        time_control: TimeControl::default(),
+        time_left: None,
+        post: false,
+        search_state: Arc::new(SearchState::default()),
+    };
+    for line in input.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if !xboard.handle(&line) {
+            return;
+        }
+    }
+    // end of input: let the engine finish its move
+    xboard.collect_move(true);
+}
+
+// thinking output for a completed iteration: ply, score, time in centiseconds, nodes and principal variation
+pub fn thinking_line(result: &SearchResult) -> String {
+    let score = match result.score {
+        Score::Centipawns(cp) => cp,
+        Score::Mate(n) if n < 0 => -MATE_SCORE - n,
+        Score::Mate(n) => MATE_SCORE - n,
+    };
+    let mut line = format!(
+        "{} {} {} {}",
+        result.depth,
+        score,
+        result.time.as_millis() / 10,
+        result.nodes + result.qnodes
+    );
+    for m in &result.pv {
+        line.push_str(&format!(" {}", m));
+    }
+    line
+}
+
+impl<W: Write + Send + 'static> XBoard<W> {
+    // returns false when the engine should quit
+    fn handle(&mut self, line: &str) -> bool {
+        // the engine's move has to be part of the position before the next command is handled
+        self.collect_move(false);
+
+        let mut tokens = line.split_whitespace();
+        let command = match tokens.next() {
+            Some(c) => c,
+            None => return true,
+        };
+        let args: Vec<&str> = tokens.collect();
+        match command {
+            "protover" => {
+                self.send("feature done=0");
+                self.send(&format!(
+                    "feature myname=\"{}\" ping=1 setboard=1 usermove=1 playother=1 san=0 \
+                     sigint=0 sigterm=0 colors=0 analyze=0 reuse=1 done=1",
+                    ENGINE_NAME
+                ));
+            }
+            "new" => {
+                self.abort_search();
+                self.engine.bot().new_game();
+                self.position = Position::default();
+                self.engine_side = Some(Color::Black);
+                self.time_control.depth = None;
+            }
+            "force" => {
+                self.abort_search();
+                self.engine_side = None;
+            }
+            "go" => {
+                self.abort_search();
+                self.engine_side = Some(self.position.board().side_to_move());
+                self.think();
+            }
+            "playother" => {
+                self.abort_search();
+                self.engine_side = Some(!self.position.board().side_to_move());
+            }
+            "usermove" => match args.first() {
+                Some(m) => self.user_move(m),
+                None => self.send("Error (missing move): usermove"),
+            },
+            "setboard" => {
+                self.abort_search();
+                match Position::from_fen(&args.join(" ")) {
+                    Ok(position) => self.position = position,
+                    Err(_) => self.send("tellusererror Illegal position"),
+                }
+            }
+            "level" => match parse_level(&args) {
+                Some((moves_per_session, base, increment)) => {
+                    self.time_control.moves_per_session = moves_per_session;
+                    self.time_control.base = base;
+                    self.time_control.increment = increment;
+                    self.time_control.move_time = None;
+                }
+                None => self.send(&format!("Error (invalid time control): {}", line)),
+            },
+            "st" => match args
+                .first()
+                .and_then(|s| s.parse::<f64>().ok())
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            {
+                Some(time) if !time.is_zero() => self.time_control.move_time = Some(time),
+                _ => self.send(&format!("Error (invalid time): {}", line)),
+            },
+            "sd" => match args.first().and_then(|s| s.parse::<u8>().ok()) {
+                Some(depth) if depth > 0 => self.time_control.depth = Some(depth),
+                _ => self.send(&format!("Error (invalid depth): {}", line)),
+            },
+            "time" => {
+                // centiseconds
+                self.time_left = args
+                    .first()
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .map(|cs| Duration::from_millis(cs * 10));
+            }
+            "undo" => {
+                self.abort_search();
+                self.position.undo();
+            }
+            "remove" => {
+                self.abort_search();
+                self.position.undo();
+                self.position.undo();
+            }
+            "result" => {
+                self.abort_search();
+                self.engine_side = None;
+            }
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "ping" => self.send(&format!("pong {}", args.first().unwrap_or(&""))),
+            // move now
+            "?" => self.engine.stop(),
+            "quit" => {
+                self.abort_search();
+                return false;
+            }
+            // not needed by this engine
+            "xboard" | "accepted" | "rejected" | "otim" | "random" | "hard" | "easy"
+            | "computer" | "name" | "rating" | "white" | "black" => {}
+            // without the usermove feature, moves are sent without a command
+            _ if self.position.parse_move(command).is_ok() => self.user_move(command),
+            _ => self.send(&format!("Error (unknown command): {}", command)),
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    fn user_move(&mut self, s: &str) {
+        // the GUI should wait for the engine's move, don't throw it away if it didn't
+        self.collect_move(true);
+        let m = match self.position.parse_move(s) {
+            Ok(m) => m,
+            Err(_) => {
+                self.send(&format!("Illegal move: {}", s));
+                return;
+            }
+        };
+        self.position
+            .make_move(m)
+            .expect("The move was checked to be legal");
+        if self.engine_side == Some(self.position.board().side_to_move()) {
+            self.think();
+        }
+    }
+
+    // start searching for the engine's move
+    fn think(&mut self) {
+        if self.position.is_game_over() {
+            return;
+        }
+        let state = Arc::new(SearchState::default());
+        self.search_state = Arc::clone(&state);
+        // full moves played in this game, for the moves-per-session time control
+        let moves_played = (self.position.moves().len() / 2) as u32;
+        let limits = self.time_control.limits(self.time_left, moves_played);
+
+        let log: Option<SearchLog> = if self.post {
+            let out = Arc::clone(&self.out);
+            Some(Box::new(move |result: &SearchResult| {
+                send(&out, &thinking_line(result))
+            }))
+        } else {
+            None
+        };
+        let out = Arc::clone(&self.out);
+        let done: SearchDone = Box::new(move |result: &SearchResult| {
+            if state.cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(m) = result.best_move {
+                // set before sending, so the move is collected before the GUI's answer is handled
+                state.moved.store(true, Ordering::Relaxed);
+                send(&out, &format!("move {}", m));
+            }
+        });
+        self.engine
+            .start(&self.position, limits, DEFAULT_DEPTH, log, done);
+    }
+
+    // stop thinking without playing a move
+    fn abort_search(&mut self) {
+        self.search_state.cancelled.store(true, Ordering::Relaxed);
+        self.engine.finish(true);
+    }
+
+    // play the engine's move once it has been sent, `wait` waits for a running search to finish
+    fn collect_move(&mut self, wait: bool) {
+        if !self.engine.is_searching() || !(wait || self.search_state.moved.load(Ordering::Relaxed))
+        {
+            return;
+        }
+        let result = match self.engine.finish(false) {
+            Some(r) => r,
+            None => return,
+        };
+        let m = match result.best_move {
+            Some(m) if !self.search_state.cancelled.load(Ordering::Relaxed) => m,
+            _ => return,
+        };
+        self.position
+            .make_move(m)
+            .expect("The bot only plays legal moves");
+
+        let board = self.position.board();
+        match board.status() {
+            BoardStatus::Checkmate if board.side_to_move() == Color::Black => {
+                self.send("1-0 {White mates}")
+            }
+            BoardStatus::Checkmate => self.send("0-1 {Black mates}"),
+            BoardStatus::Stalemate => self.send("1/2-1/2 {Stalemate}"),
+            BoardStatus::Ongoing => {}
+        }
+    }
+}
+
+// level <moves per session> <base time in minutes or minutes:seconds> <increment in seconds>
+fn parse_level(args: &[&str]) -> Option<(Option<u32>, Duration, Duration)> {
+    if args.len() != 3 {
+        return None;
+    }
+    let moves: u32 = args[0].parse().ok()?;
+    let mut base = args[1].split(':');
+    let minutes: u64 = base.next()?.parse().ok()?;
+    let seconds: u64 = match base.next() {
+        Some(s) => s.parse().ok()?,
+        None => 0,
+    };
+    let base = minutes.checked_mul(60)?.checked_add(seconds)?;
+    let increment: f64 = args[2].parse().ok()?;
+    Some((
+        if moves == 0 { None } else { Some(moves) },
+        Duration::from_secs(base),
+        Duration::try_from_secs_f64(increment.max(0.)).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // collects the output of the engine
+    #[derive(Clone, Default)]
+    struct Transcript(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Transcript {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_script(script: &str) -> Vec<String> {
+        let transcript = Transcript::default();
+        run(script.as_bytes(), transcript.clone());
+        let output = transcript.0.lock().unwrap().clone();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn feature_negotiation() {
+        let output = run_script("xboard\nprotover 2\nping 7\n");
+        assert_eq!(output[0], "feature done=0");
+        assert!(output[1].starts_with("feature myname="));
+        assert!(output[1].contains("setboard=1") && output[1].ends_with("done=1"));
+        assert_eq!(output[2], "pong 7");
+    }
+
+    #[test]
+    fn engine_replies_to_user_move() {
+        let output = run_script("xboard\nnew\nsd 2\nusermove e2e4\n");
+        assert_eq!(output.len(), 1);
+        assert!(output[0].starts_with("move "));
+    }
+
+    #[test]
+    fn go_with_thinking_output() {
+        let output =
+            run_script("new\nforce\nsetboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\npost\nsd 3\ngo\n");
+        assert!(output[0].starts_with("1 "));
+        assert!(output.iter().any(|l| l.starts_with("1 99999 ")));
+        assert_eq!(output[output.len() - 2], "move a1a8");
+        assert_eq!(output[output.len() - 1], "1-0 {White mates}");
+    }
+
+    #[test]
+    fn force_undo_and_illegal_moves() {
+        let output = run_script("new\nforce\nusermove e2e4\nundo\nusermove e2e5\nd2d4\n");
+        assert_eq!(output, vec!["Illegal move: e2e5"]);
+    }
+
+    #[test]
+    fn engine_plays_both_sides_of_a_game() {
+        // the engine moves for white, then the user answers and the engine moves again
+        let output = run_script("new\nlevel 40 0:30 0\nst 0.05\nsd 2\ngo\nusermove e7e5\n");
+        let moves: Vec<&String> = output.iter().filter(|l| l.starts_with("move ")).collect();
+        assert_eq!(moves.len(), 2, "{:?}", output);
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(
+            parse_level(&["40", "5", "0"]),
+            Some((Some(40), Duration::from_secs(300), Duration::ZERO))
+        );
+        assert_eq!(
+            parse_level(&["0", "2:30", "1.5"]),
+            Some((None, Duration::from_secs(150), Duration::from_millis(1500)))
+        );
+        assert_eq!(parse_level(&["0", "x", "1"]), None);
+        assert_eq!(parse_level(&["40", "5", "inf"]), None);
+        assert_eq!(parse_level(&["40", "5", "1e300"]), None);
+        assert_eq!(parse_level(&["40", "999999999999999999", "0"]), None);
+    }
+
+    #[test]
+    fn invalid_times() {
+        let output = run_script("st inf\nst 1e300\nst 0\nlevel 40 5 inf\nst 2\n");
+        assert_eq!(
+            output,
+            vec![
+                "Error (invalid time): st inf",
+                "Error (invalid time): st 1e300",
+                "Error (invalid time): st 0",
+                "Error (invalid time control): level 40 5 inf",
+            ]
+        );
+    }
+}
@@ -0,0 +1,376 @@
+// game setup from commandline arguments, for scripted games that can't answer the setup questions
+use chess::Game;
+use chess_ai::external::{EngineConfig, DEFAULT_MOVE_TIME};
+use chess_ai::history::fen_halfmove_clock;
+use chess_ai::record::UndoPolicy;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use crate::{Command, GameSetup, GameVisual, MatchSetup, PlayerConfig, ReplaySetup};
+
+pub const USAGE: &str = "\
+Usage: playchess [--white <player>] [--black <player>] [--fen <fen>] [--clock <time control>]
+                 [--undo ply|reply] [--ui commandline|gui] [--pgn-out <file>]
+       playchess --replay <pgn file> [--game <n>] [--ui commandline|gui]
+       playchess --match <games> [--player1 <player>] [--player2 <player>] [--openings <file>]
+                 [--clock <time control>] [--concurrency <n>] [--pgn-out <file>]
+                 [--sprt <settings>] [--results-out <file>]
+Without arguments the game is set up interactively.
+
+Players:
+  human
+  bot[:depth=<n>,time=<ms>,debug]
+  engine:path=<executable>[,depth=<n>,movetime=<ms>,<UCI option>=<value>...]
+
+Time controls, in seconds:
+  300         sudden death
+  180+2       Fischer increment per move
+  300d5       simple delay
+  300b5       Bronstein delay
+  40/5400+30  5400 seconds for every 40 moves, with any of the above increments
+Bots and engines take their time per move from their clock.
+
+Humans take back moves with u in the commandline or the U key in the gui, and redo them with r or R.
+--undo reply (the default) takes back the reply of a bot or engine as well, --undo ply a single ply.
+Moves can't be taken back in games with a clock.
+
+Humans resign with resign, offer a draw with draw before their move, answer an offer with accept or
+decline, and claim a draw by threefold repetition or the 50-move rule with claim. In the gui these are
+the keys Q, D, A, N and C.
+
+The game is saved to the PGN file after every move.
+
+--match plays games between two bots or engines without a board, with the colors alternating.
+The games start from the positions of the openings file, a FEN or EPD per line, each played with both
+colors. --concurrency games are played at the same time, by default one per core. The result and the
+Elo difference are printed as the games finish, and all games are saved to the PGN file.
+
+--sprt stops the match once a sequential probability ratio test decides whether player1 is stronger:
+  elo0=<elo>,elo1=<elo>[,alpha=<p>,beta=<p>,model=trinomial|pentanomial]
+It accepts elo1 (player1 is at least elo1 stronger) or elo0 (at most elo0), wrongly only with the
+probabilities alpha and beta. Defaults: elo0=0,elo1=5,alpha=0.05,beta=0.05,model=pentanomial, where
+pentanomial counts the results of game pairs played from the same opening. With --sprt, --match is
+the maximum number of games and can be left out. --results-out writes the results as JSON at the end.
+Defaults: --white human --black bot --ui commandline, from the starting position.
+Matches default to --player1 bot --player2 bot from the starting position.";
+
+const DEFAULT_BOT_DEPTH: u8 = 3;
+
+// parse the arguments, without the program name
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut setup = GameSetup {
+        white: PlayerConfig::Human,
+        black: bot_config(&[])?,
+        game: Game::new(),
+        halfmove_clock: 0,
+        visual: GameVisual::CommandLine,
+        pgn_out: None,
+        clock: None,
+        undo: UndoPolicy::default(),
+    };
+    let mut replay = None;
+    let mut game_number = None;
+    let mut games = None;
+    let mut players = [bot_config(&[])?, bot_config(&[])?];
+    let mut names = ["bot".to_string(), "bot".to_string()];
+    let mut openings = None;
+    let mut concurrency = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut sprt = None;
+    let mut results_out = None;
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", flag))
+        };
+        match flag.as_str() {
+            "--white" => setup.white = parse_player(value()?)?,
+            "--black" => setup.black = parse_player(value()?)?,
+            "--fen" => (setup.game, setup.halfmove_clock) = parse_fen(value()?)?,
+            "--ui" => setup.visual = parse_visual(value()?)?,
+            "--pgn-out" => setup.pgn_out = Some(PathBuf::from(value()?)),
+            "--clock" => setup.clock = Some(value()?.parse()?),
+            "--undo" => setup.undo = parse_undo(value()?)?,
+            "--replay" => replay = Some(PathBuf::from(value()?)),
+            "--game" => game_number = Some(parse_number(flag, value()?)?),
+            "--match" => games = Some(parse_number(flag, value()?)?),
+            "--player1" | "--player2" => {
+                let i = if flag == "--player1" { 0 } else { 1 };
+                let spec = value()?;
+                players[i] = parse_player(spec)?;
+                names[i] = spec.clone();
+            }
+            "--openings" => openings = Some(PathBuf::from(value()?)),
+            "--concurrency" => concurrency = parse_number(flag, value()?)?,
+            "--sprt" => sprt = Some(value()?.parse()?),
+            "--results-out" => results_out = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("Unknown argument: {}", flag)),
+        }
+        flags.push(flag.as_str());
+    }
+    // the first of the flags that was given
+    let given = |names: &[&'static str]| names.iter().find(|f| flags.contains(f)).copied();
+    let match_options = [
+        "--player1",
+        "--player2",
+        "--openings",
+        "--concurrency",
+        "--results-out",
+    ];
+    let game_options = ["--white", "--black", "--fen", "--undo", "--game"];
+    if games.is_some() || sprt.is_some() {
+        if let Some(flag) = given(&game_options).or_else(|| given(&["--ui", "--replay"])) {
+            return Err(format!("{} can't be used in matches", flag));
+        }
+        if games == Some(0) || concurrency == 0 {
+            return Err("Matches need at least one game and one game at a time".to_string());
+        }
+        if players.contains(&PlayerConfig::Human) {
+            return Err("Matches are played between bots and engines".to_string());
+        }
+        return Ok(Command::Match(MatchSetup {
+            players,
+            names,
+            games,
+            openings,
+            clock: setup.clock,
+            concurrency,
+            pgn_out: setup.pgn_out,
+            sprt,
+            results_out,
+        }));
+    }
+    if let Some(flag) = given(&match_options) {
+        return Err(format!("{} only works with --match or --sprt", flag));
+    }
+    let new_game = given(&game_options[..4]).or_else(|| given(&["--clock", "--pgn-out"]));
+    match replay {
+        Some(_) if new_game.is_some() => {
+            Err("--replay can't be combined with options for a new game".to_string())
+        }
+        Some(pgn) => Ok(Command::Replay(ReplaySetup {
+            pgn,
+            game: game_number.unwrap_or(1),
+            visual: setup.visual,
+        })),
+        None if game_number.is_some() => Err("--game only works with --replay".to_string()),
+        None => Ok(Command::Play(setup)),
+    }
+}
+
+// the game and the halfmove clock of the FEN, chess::Game drops it
+pub fn parse_fen(fen: &str) -> Result<(Game, u32), String> {
+    let game = Game::from_str(fen.trim()).map_err(|_| format!("Invalid FEN: {}", fen))?;
+    Ok((game, fen_halfmove_clock(fen)))
+}
+
+pub fn parse_visual(s: &str) -> Result<GameVisual, String> {
+    match s {
+        "commandline" | "cli" => Ok(GameVisual::CommandLine),
+        "gui" => Ok(GameVisual::Gui),
+        _ => Err(format!(
+            "Invalid ui '{}', should be 'commandline' or 'gui'",
+            s
+        )),
+    }
+}
+
+pub fn parse_undo(s: &str) -> Result<UndoPolicy, String> {
+    match s {
+        "ply" => Ok(UndoPolicy::Ply),
+        "reply" => Ok(UndoPolicy::WithReply),
+        _ => Err(format!(
+            "Invalid undo policy '{}', should be 'ply' or 'reply'",
+            s
+        )),
+    }
+}
+
+// human, bot[:key=value,...] or engine:key=value,...
+pub fn parse_player(spec: &str) -> Result<PlayerConfig, String> {
+    let (kind, params) = spec.split_once(':').unwrap_or((spec, ""));
+    let params: Vec<(&str, &str)> = params
+        .split(',')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.split_once('=').unwrap_or((p, "")))
+        .collect();
+    match kind {
+        "human" if params.is_empty() => Ok(PlayerConfig::Human),
+        "human" => Err("Human players have no options".to_string()),
+        "bot" => bot_config(&params),
+        "engine" => engine_config(&params),
+        _ => Err(format!(
+            "Invalid player '{}', should be 'human', 'bot' or 'engine'",
+            kind
+        )),
+    }
+}
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: '{}'", key, value))
+}
+
+fn bot_config(params: &[(&str, &str)]) -> Result<PlayerConfig, String> {
+    let mut depth = DEFAULT_BOT_DEPTH;
+    let mut time_limit = None;
+    let mut debug = false;
+    for (key, value) in params {
+        match *key {
+            "depth" => depth = parse_number(key, value)?,
+            "time" => time_limit = Some(Duration::from_millis(parse_number(key, value)?)),
+            "debug" => debug = matches!(*value, "" | "true" | "yes"),
+            _ => return Err(format!("Unknown bot option: {}", key)),
+        }
+    }
+    Ok(PlayerConfig::Bot {
+        depth,
+        time_limit,
+        debug,
+    })
+}
+
+// options other than path, depth and movetime are passed on to the engine
+fn engine_config(params: &[(&str, &str)]) -> Result<PlayerConfig, String> {
+    let mut config = EngineConfig::default();
+    for (key, value) in params {
+        match *key {
+            "path" => config.path = PathBuf::from(value),
+            "depth" => config.depth = Some(parse_number(key, value)?),
+            "movetime" => config.move_time = Some(Duration::from_millis(parse_number(key, value)?)),
+            _ => config.options.push((key.to_string(), value.to_string())),
+        }
+    }
+    if config.path.as_os_str().is_empty() {
+        return Err("Engine players need a path, e.g. engine:path=/usr/bin/stockfish".to_string());
+    }
+    if config.depth.is_none() && config.move_time.is_none() {
+        config.move_time = Some(DEFAULT_MOVE_TIME);
+    }
+    Ok(PlayerConfig::Engine(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn full_setup() {
+        let mut a = args(
+            "--white bot:depth=5,time=200,debug --black human --ui gui --pgn-out games.pgn --clock 180+2 --undo ply --fen",
+        );
+        a.push("6k1/5ppp/8/8/8/8/8/R5K1 w - - 12 40".to_string());
+        let setup = match parse_args(&a).unwrap() {
+            Command::Play(setup) => setup,
+            _ => panic!("Expected a new game"),
+        };
+        assert_eq!(
+            setup.white,
+            PlayerConfig::Bot {
+                depth: 5,
+                time_limit: Some(Duration::from_millis(200)),
+                debug: true
+            }
+        );
+        assert_eq!(setup.black, PlayerConfig::Human);
+        assert_eq!(setup.visual, GameVisual::Gui);
+        assert_eq!(setup.pgn_out, Some(PathBuf::from("games.pgn")));
+        assert_eq!(setup.clock, Some("180+2".parse().unwrap()));
+        assert_eq!(setup.undo, UndoPolicy::Ply);
+        assert_eq!(
+            setup.game.current_position().to_string(),
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"
+        );
+        assert_eq!(setup.halfmove_clock, 12);
+    }
+
+    #[test]
+    fn replay() {
+        match parse_args(&args("--replay games.pgn --game 3 --ui gui")).unwrap() {
+            Command::Replay(replay) => {
+                assert_eq!(replay.pgn, PathBuf::from("games.pgn"));
+                assert_eq!(replay.game, 3);
+                assert_eq!(replay.visual, GameVisual::Gui);
+            }
+            _ => panic!("Expected a replay"),
+        }
+    }
+
+    #[test]
+    fn match_setup() {
+        let a = args("--match 100 --player1 bot:depth=4 --openings book.epd --clock 10+0.1 --concurrency 4 --pgn-out match.pgn");
+        let setup = match parse_args(&a).unwrap() {
+            Command::Match(setup) => setup,
+            _ => panic!("Expected a match"),
+        };
+        assert_eq!(setup.games, Some(100));
+        assert_eq!(setup.names, ["bot:depth=4".to_string(), "bot".to_string()]);
+        assert_eq!(
+            setup.players[0],
+            PlayerConfig::Bot {
+                depth: 4,
+                time_limit: None,
+                debug: false
+            }
+        );
+        assert_eq!(setup.players[1], bot_config(&[]).unwrap());
+        assert_eq!(setup.openings, Some(PathBuf::from("book.epd")));
+        assert_eq!(setup.clock, Some("10+0.1".parse().unwrap()));
+        assert_eq!(setup.concurrency, 4);
+        assert_eq!(setup.pgn_out, Some(PathBuf::from("match.pgn")));
+        assert_eq!(setup.sprt, None);
+
+        let a = args("--sprt elo0=-5,elo1=0,model=trinomial --results-out sprt.json");
+        let setup = match parse_args(&a).unwrap() {
+            Command::Match(setup) => setup,
+            _ => panic!("Expected a match"),
+        };
+        assert_eq!(setup.games, None);
+        let sprt = setup.sprt.unwrap();
+        assert_eq!((sprt.elo0, sprt.elo1, sprt.alpha), (-5.0, 0.0, 0.05));
+        assert_eq!(setup.results_out, Some(PathBuf::from("sprt.json")));
+    }
+
+    #[test]
+    fn engine_player() {
+        let config = parse_player("engine:path=/usr/bin/sf,Hash=64,depth=8").unwrap();
+        assert_eq!(
+            config,
+            PlayerConfig::Engine(EngineConfig {
+                path: PathBuf::from("/usr/bin/sf"),
+                options: vec![("Hash".to_string(), "64".to_string())],
+                move_time: None,
+                depth: Some(8),
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_args(&args("--white")).is_err());
+        assert!(parse_args(&args("--white robot")).is_err());
+        assert!(parse_args(&args("--black bot:depth=deep")).is_err());
+        assert!(parse_args(&args("--ui terminal")).is_err());
+        assert!(parse_args(&args("--fen nonsense")).is_err());
+        assert!(parse_args(&args("--colour white")).is_err());
+        assert!(parse_args(&args("--game 2")).is_err());
+        assert!(parse_args(&args("--replay a.pgn --white human")).is_err());
+        assert!(parse_args(&args("--clock 5min")).is_err());
+        assert!(parse_args(&args("--undo all")).is_err());
+        assert!(parse_args(&args("--match 10 --white bot")).is_err());
+        assert!(parse_args(&args("--match 10 --player2 human")).is_err());
+        assert!(parse_args(&args("--match 0")).is_err());
+        assert!(parse_args(&args("--openings book.epd")).is_err());
+        assert!(parse_args(&args("--sprt elo0=5,elo1=1")).is_err());
+        assert!(parse_args(&args("--results-out r.json")).is_err());
+        assert!(parse_player("engine:depth=3").is_err());
+    }
+}
@@ -8,12 +8,26 @@ mod tests {
 }
 
 // imports
-use chess::{self, Board, ChessMove, Color, Game, Piece, Square};
-use chess_ai::Bot;
-use chess_gui::{self, GameState};
+use chess::{self, Board, Color, Game, Piece, Square};
+use chess_ai::arena::{parse_openings, run_match, MatchSettings};
+use chess_ai::clock::{format_time, Clock, ClockSettings};
+use chess_ai::external::{EngineConfig, ExternalEngine, DEFAULT_MOVE_TIME};
+use chess_ai::outcome::Outcome;
+use chess_ai::pgn::{numbered_move, parse_pgn, to_pgn, PgnGame, PgnHeader};
+use chess_ai::player::{wait_for_action, BotPlayer, EnginePlayer, Player, PlayerAction};
+use chess_ai::record::{GameRecord, UndoPolicy};
+use chess_ai::san::{line_to_san, parse_san, to_san};
+use chess_ai::stats::{half_points, MatchReport, PairScore, Sprt, SprtResult};
+use chess_ai::{Bot, SearchResult};
+use chess_gui::{self, GameState, GuiHuman};
 use ggez::GameResult;
-use std::io;
-use std::str::FromStr;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+mod args;
+pub use args::{parse_args, USAGE};
 
 // chessboard squares
 const SQUARES: [[Square; 8]; 8] = [
@@ -99,8 +113,8 @@ const SQUARES: [[Square; 8]; 8] = [
     ],
 ];
 
-// display board in commandline
-fn print_board(board: &Board) {
+// display board in commandline, with the clocks of games that have them
+fn print_board(board: &Board, clock: Option<&Clock>) {
     let mut rank = 8;
     println!("  -------------------------");
     for row in SQUARES {
@@ -131,20 +145,84 @@ fn print_board(board: &Board) {
         println!("  -------------------------");
     }
     println!("   a  b  c  d  e  f  g  h");
+    if let Some(clock) = clock {
+        println!(
+            "White {}  Black {}",
+            format_time(clock.remaining(Color::White)),
+            format_time(clock.remaining(Color::Black))
+        );
+    }
 }
 
-#[derive(PartialEq)]
-pub enum PlayerType {
+// configuration of a player, the player is created when the game starts since humans play differently in the gui
+#[derive(Debug, PartialEq)]
+pub enum PlayerConfig {
     Human,
-    Bot,
+    Bot {
+        depth: u8,
+        time_limit: Option<Duration>,
+        debug: bool,
+    },
+    Engine(EngineConfig),
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum GameVisual {
     CommandLine,
     Gui,
 }
 
+// what playchess should do
+pub enum Command {
+    Play(GameSetup),
+    Replay(ReplaySetup),
+    Match(MatchSetup),
+}
+
+// step through a game of a PGN file
+pub struct ReplaySetup {
+    pub pgn: PathBuf,
+    // number of the game in the file, starting at 1
+    pub game: usize,
+    pub visual: GameVisual,
+}
+
+// games between two bots or engines without a board, to compare them
+pub struct MatchSetup {
+    pub players: [PlayerConfig; 2],
+    // the players as given on the commandline, to tell them apart in the results
+    pub names: [String; 2],
+    // None to play until the SPRT decides
+    pub games: Option<usize>,
+    // file with a FEN or EPD per line, the games start from the starting position without one
+    pub openings: Option<PathBuf>,
+    pub clock: Option<ClockSettings>,
+    // games played at the same time
+    pub concurrency: usize,
+    // all games are saved there as they finish
+    pub pgn_out: Option<PathBuf>,
+    // stop the match when the test decides
+    pub sprt: Option<Sprt>,
+    // the results are written there as JSON at the end
+    pub results_out: Option<PathBuf>,
+}
+
+// everything needed to start a game
+pub struct GameSetup {
+    pub white: PlayerConfig,
+    pub black: PlayerConfig,
+    pub game: Game,
+    // of the starting position, chess::Game doesn't keep it
+    pub halfmove_clock: u32,
+    pub visual: GameVisual,
+    // the game is saved there after every move
+    pub pgn_out: Option<PathBuf>,
+    // None for games without time control
+    pub clock: Option<ClockSettings>,
+    // what humans take back with undo
+    pub undo: UndoPolicy,
+}
+
 // get a string from stdin
 fn stdin_get_input() -> String {
     let stdin = io::stdin();
@@ -158,64 +236,115 @@ fn print_san_help() {
     println!("-------------------------------------------------------");
     println!("Please enter a valid move in SAN format.");
     println!("Capture: exd5 or Nxc6");
-    println!("If the move results in a check: add + at the end");
-    println!("En passant: add (ep) at the end");
+    println!("If the move results in a check: add + at the end (optional)");
+    println!("En passant: like a normal capture, exd6");
     println!("Promotion: add =Q at the end, replace Q with the \npiece you want to promote to");
     println!("To disambiguate between two pieces, e.g. both Rooks \ncould take on c1: Raxc1 to specify the rook on the a file");
-    println!("Checkmate: add ++ at the end");
+    println!("Checkmate: add # at the end (optional)");
     println!("Castle kingside / queenside: O-O / O-O-O");
     println!("-------------------------------------------------------");
+    println!("u: take back moves, r: play them again");
+    println!("resign: resign the game");
+    println!("draw: offer a draw, then enter your move");
+    println!("accept / decline: answer the draw your opponent offered");
+    println!("claim: claim a draw by threefold repetition or the 50-move rule");
+    println!("-------------------------------------------------------");
 }
 
-// get a move from the player through stdin
-fn get_move_stdin(board: Board) -> ChessMove {
-    println!("Enter the next move (in SAN): ");
-    let mut _move;
+// get a move, or a takeback, from the player through stdin
+fn get_action_stdin(board: Board) -> PlayerAction {
+    println!("Enter the next move (in SAN), or u, r, resign, draw, accept, decline or claim (help for more): ");
     loop {
-        let input = stdin_get_input();
-        _move = ChessMove::from_san(&board, &input);
-        match _move {
-            Ok(_) => break,
-            Err(_) => print_san_help(),
+        match stdin_get_input().as_str() {
+            "u" => return PlayerAction::Undo,
+            "r" => return PlayerAction::Redo,
+            "resign" => return PlayerAction::Resign,
+            "draw" => return PlayerAction::OfferDraw,
+            "accept" => return PlayerAction::AcceptDraw,
+            "decline" => return PlayerAction::DeclineDraw,
+            "claim" => return PlayerAction::ClaimDraw,
+            "help" => print_san_help(),
+            input => match parse_san(&board, input) {
+                Ok(m) => return PlayerAction::Move(m),
+                Err(e) => {
+                    println!("{}", e);
+                    print_san_help();
+                }
+            },
         }
     }
-    _move.expect("Please enter a valid move in SAN format!")
 }
 
-pub struct Player {
-    player_type: PlayerType,
-    color: Color,
-    bot_ref: Bot,
-}
+// human player entering moves in the commandline
+pub struct StdinHuman;
 
-impl Player {
-    fn new_human(color: Color) -> Player {
-        Player {
-            player_type: PlayerType::Human,
-            color: color,
-            bot_ref: Bot::new(color, 0, false),
-        }
+impl Player for StdinHuman {
+    fn name(&self) -> String {
+        "Human".to_string()
     }
 
-    fn new_bot(color: Color, depth: u8, debug: bool) -> Player {
-        Player {
-            player_type: PlayerType::Bot,
-            color: color,
-            bot_ref: Bot::new(color, depth, debug),
-        }
+    fn choose_move(&mut self, game: &Game) -> PlayerAction {
+        get_action_stdin(game.current_position())
     }
 
-    fn get_move(&self, board: Board) -> ChessMove {
-        if self.player_type == PlayerType::Human {
-            get_move_stdin(board)
-        } else {
-            self.bot_ref.get_move(board)
-        }
+    fn accepts_input(&self) -> bool {
+        true
+    }
+}
+
+impl PlayerConfig {
+    fn create(&self, color: Color, visual: &GameVisual) -> io::Result<Box<dyn Player>> {
+        let player: Box<dyn Player> = match self {
+            PlayerConfig::Human => match visual {
+                GameVisual::CommandLine => Box::new(StdinHuman),
+                GameVisual::Gui => Box::new(GuiHuman::new()),
+            },
+            PlayerConfig::Bot {
+                depth,
+                time_limit,
+                debug,
+            } => {
+                let mut bot = Bot::new(color, *depth);
+                bot.set_time_limit(*time_limit);
+                if *debug {
+                    // print every iteration of the search
+                    bot.set_log(Some(Box::new(|result: &SearchResult| {
+                        println!("{}", result)
+                    })));
+                }
+                Box::new(BotPlayer::new(bot))
+            }
+            PlayerConfig::Engine(config) => {
+                Box::new(EnginePlayer::new(ExternalEngine::start(config)?))
+            }
+        };
+        Ok(player)
     }
 }
 
-fn bot_setup(color: Color) -> Player {
-    // TODO: allow commandline configuration of bot
+// display the result of a bot's search in the commandline, `board` is the position searched
+fn print_search_result(result: &SearchResult, board: &Board) {
+    let score = if board.side_to_move() == Color::White {
+        result.score
+    } else {
+        result.score.flip()
+    };
+    println!();
+    println!(
+        "Searched {} nodes ({} quiescence nodes) in {:.2?}, depth {}",
+        result.nodes, result.qnodes, result.time, result.depth
+    );
+    println!(
+        "Score for current position (white's perspective): {}",
+        score
+    );
+    println!("Expected line: {}", line_to_san(board, &result.pv));
+    if let Some(m) = result.best_move {
+        println!("Move chosen: {}", to_san(board, m));
+    }
+}
+
+fn bot_setup() -> PlayerConfig {
     println!("--- BOT setup ---");
     println!("Search depth: ");
     let depth: u8 = if let Ok(d) = stdin_get_input().parse() {
@@ -224,21 +353,59 @@ fn bot_setup(color: Color) -> Player {
         3
     };
 
+    println!("Time limit per move in milliseconds (leave empty for no limit): ");
+    let time_limit = stdin_get_input().parse().ok().map(Duration::from_millis);
+
     println!("Debug? y/n ");
     let mut debug = false;
-    if stdin_get_input(&stdin) == "y" {
+    if stdin_get_input() == "y" {
         debug = true;
     }
 
     println!("-----------------");
-    Player::new_bot(color, depth, debug)
+    PlayerConfig::Bot {
+        depth,
+        time_limit,
+        debug,
+    }
+}
+
+fn engine_setup() -> PlayerConfig {
+    println!("--- ENGINE setup ---");
+    println!("Path to the UCI engine executable: ");
+    let path = PathBuf::from(stdin_get_input());
+
+    println!("Engine options as Name=Value, separated by commas (leave empty for none): ");
+    let options = stdin_get_input()
+        .split(',')
+        .filter_map(|option| option.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    println!("Search depth (leave empty for no limit): ");
+    let depth = stdin_get_input().parse().ok();
+
+    println!("Time per move in milliseconds (leave empty for 1000 if there is no depth): ");
+    let mut move_time = stdin_get_input().parse().ok().map(Duration::from_millis);
+    if depth.is_none() && move_time.is_none() {
+        move_time = Some(DEFAULT_MOVE_TIME);
+    }
+
+    println!("--------------------");
+    PlayerConfig::Engine(EngineConfig {
+        path,
+        options,
+        move_time,
+        depth,
+    })
 }
 
 // configure a player
-fn stdin_get_player(color: Color) -> std::result::Result<Player, ()> {
+fn stdin_get_player() -> std::result::Result<PlayerConfig, ()> {
     match stdin_get_input().as_str() {
-        "human" => Ok(Player::new_human(color)),
-        "bot" => Ok(bot_setup(color)),
+        "human" => Ok(PlayerConfig::Human),
+        "bot" => Ok(bot_setup()),
+        "engine" => Ok(engine_setup()),
         _ => Err(()),
     }
 }
@@ -254,97 +421,424 @@ fn trim_newline(s: &mut String) {
 }
 
 // game setup through commandline
-pub fn command_line_setup() -> (Player, Player, Game, GameVisual) {
-    // player 1
-    println!("Select player 1: human or bot.");
+pub fn command_line_setup() -> Result<GameSetup, String> {
+    let invalid_player = |_| "Invalid input should be 'human', 'bot' or 'engine'.".to_string();
 
-    let player1 = match stdin_get_player(Color::White) {
-        Ok(player) => player,
-        Err(_) => {
-            println!("Invalid input should be 'human' or 'bot'.");
-            std::process::exit(1);
-        }
-    };
+    // player 1
+    println!("Select player 1: human, bot or engine.");
+    let white = stdin_get_player().map_err(invalid_player)?;
 
     // player 2
-    println!("Select player 2: human or bot.");
-
-    let player2 = match stdin_get_player(Color::Black) {
-        Ok(player) => player,
-        Err(_) => {
-            println!("Invalid input should be 'human' or 'bot'.");
-            std::process::exit(1);
-        }
-    };
+    println!("Select player 2: human, bot or engine.");
+    let black = stdin_get_player().map_err(invalid_player)?;
 
     // board position
     println!("Do you want to play from the default starting position or a specific FEN?");
 
-    let game = match stdin_get_input().as_str() {
-        "default" => Game::new(),
+    let (game, halfmove_clock) = match stdin_get_input().as_str() {
+        "default" => (Game::new(), 0),
         _ => {
             println!("Enter FEN:");
-            let fen = stdin_get_input();
-            let board = Board::from_str(&fen).expect("Valid FEN");
-            Game::new_with_board(board)
+            args::parse_fen(&stdin_get_input())?
         }
     };
 
+    // time control
+    println!("Time control in seconds, e.g. 300, 180+2, 300d5, 300b5 or 40/5400+30 (leave empty for none):");
+    let clock = match stdin_get_input().as_str() {
+        "" => None,
+        input => Some(input.parse()?),
+    };
+
     // visualization
     println!("Do yo want to play in the commandline or gui?");
+    let visual = args::parse_visual(&stdin_get_input())?;
 
-    match stdin_get_input().as_str() {
-        "commandline" => (player1, player2, game, GameVisual::CommandLine),
-        "gui" => (player1, player2, game, GameVisual::Gui),
-        _ => {
-            println!("Invalid input should be 'commandline' or 'gui'.");
-            std::process::exit(1);
-        }
-    }
+    Ok(GameSetup {
+        white,
+        black,
+        game,
+        halfmove_clock,
+        visual,
+        pgn_out: None,
+        clock,
+        undo: UndoPolicy::default(),
+    })
 }
 
 // start the configured game
-pub fn start_game(
-    player1: Player,
-    player2: Player,
-    mut game: Game,
-    visual: GameVisual,
-) -> GameResult {
+pub fn start_game(setup: GameSetup) -> GameResult {
+    let GameSetup {
+        white,
+        black,
+        game,
+        halfmove_clock,
+        visual,
+        pgn_out,
+        clock,
+        undo,
+    } = setup;
+    let create = |config: &PlayerConfig, color: Color| {
+        config
+            .create(color, &visual)
+            .map_err(|e| ggez::GameError::CustomError(format!("Could not start player: {}", e)))
+    };
+    let mut players = [create(&white, Color::White)?, create(&black, Color::Black)?];
+
     if visual == GameVisual::CommandLine {
         // game loop in commandline
-        while game.result().is_none() {
-            print_board(&game.current_position());
-            if game.side_to_move() == Color::White {
-                game.make_move(player1.get_move(game.current_position()));
-            } else {
-                game.make_move(player2.get_move(game.current_position()));
+        let mut record = GameRecord::new(game, halfmove_clock);
+        let start = *record.start();
+        for player in players.iter_mut() {
+            player.new_game(&start, halfmove_clock);
+        }
+        let header = PgnHeader::new(&players[0].name(), &players[1].name());
+        let mut clock = clock.map(Clock::new);
+        let mut outcome = None;
+        print_board(&start, clock.as_ref());
+        loop {
+            let color = record.game().side_to_move();
+            let action = match outcome {
+                Some(outcome) => {
+                    println!("{}", outcome);
+                    // humans can still take back moves of a finished game
+                    if clock.is_some() || !players.iter().any(|p| p.accepts_input()) {
+                        break;
+                    }
+                    println!("Enter u to take back moves, anything else to quit");
+                    if stdin_get_input() != "u" {
+                        break;
+                    }
+                    PlayerAction::Undo
+                }
+                None => {
+                    let player = &mut players[color.to_index()];
+                    if let Some(clock) = &mut clock {
+                        if clock.running().is_none() {
+                            clock.start(color);
+                            player.set_clock(clock.limits(color));
+                        }
+                    }
+                    if record.draw_offered() && player.accepts_input() {
+                        println!("Your opponent offers a draw, enter accept or decline");
+                    }
+                    wait_for_action(player.as_mut(), record.game())
+                }
+            };
+            // the clock stops with the move, the flag can fall before any other action
+            let in_time = match (&mut clock, action) {
+                (Some(clock), PlayerAction::Move(_)) => clock.press(),
+                (Some(clock), _) => !clock.flagged(color),
+                (None, _) => true,
+            };
+            let player = &players[color.to_index()];
+            match action {
+                _ if !in_time => {
+                    outcome = Some(Outcome::time_forfeit(
+                        &record.game().current_position(),
+                        color,
+                    ));
+                }
+                PlayerAction::Move(m) => {
+                    let board = record.game().current_position();
+                    let search = player.search_result().cloned();
+                    if let Some(result) = &search {
+                        print_search_result(result, &board);
+                    }
+                    if board.legal(m) {
+                        println!(
+                            "{} plays {}",
+                            player.name(),
+                            numbered_move(&start, record.ply(), &board, m)
+                        );
+                        record.make_move(m, search);
+                    } else {
+                        println!("{} played an illegal move: {}", player.name(), m);
+                        record.resign(color);
+                    }
+                    print_board(&record.game().current_position(), clock.as_ref());
+                }
+                PlayerAction::Resign | PlayerAction::Wait => {
+                    if let Some(e) = player.error() {
+                        println!("{}", e);
+                    }
+                    record.resign(color);
+                }
+                PlayerAction::OfferDraw => {
+                    if record.offer_draw(color) {
+                        println!("{} offers a draw", player.name());
+                    }
+                }
+                PlayerAction::AcceptDraw => {
+                    if !record.accept_draw() {
+                        println!("There is no draw offer to accept");
+                    }
+                }
+                PlayerAction::DeclineDraw => {
+                    if record.decline_draw() {
+                        println!("{} declines the draw", player.name());
+                    } else {
+                        println!("There is no draw offer to decline");
+                    }
+                }
+                PlayerAction::ClaimDraw => {
+                    if !record.claim_draw() {
+                        println!("No draw can be claimed, the position hasn't occurred three times and there were captures or pawn moves in the last 50 moves");
+                    }
+                }
+                PlayerAction::Undo | PlayerAction::Redo if clock.is_some() => {
+                    println!("Moves can't be taken back in games with a clock");
+                }
+                PlayerAction::Undo | PlayerAction::Redo => {
+                    let human = |color: Color| players[color.to_index()].accepts_input();
+                    let plies = if action == PlayerAction::Undo {
+                        record.undo_with(undo, human)
+                    } else {
+                        record.redo_with(undo, human)
+                    };
+                    match (action, plies) {
+                        (PlayerAction::Undo, 0) => println!("Nothing to take back"),
+                        (PlayerAction::Undo, _) => println!("Took back {} plies", plies),
+                        (_, 0) => println!("Nothing to redo"),
+                        _ => println!("Played {} plies again", plies),
+                    }
+                    print_board(&record.game().current_position(), clock.as_ref());
+                }
+            }
+            if in_time {
+                outcome = record.outcome();
+            }
+            if let Some(path) = &pgn_out {
+                let pgn = to_pgn(&header, &start, record.game(), outcome, record.searches());
+                if let Err(e) = fs::write(path, pgn) {
+                    println!("Could not save the game to {}: {}", path.display(), e);
+                }
             }
         }
-        print_board(&game.current_position());
-        match game.result() {
-            Some(chess::GameResult::WhiteCheckmates) => println!("Checkmate! Winner: White"),
-            Some(chess::GameResult::BlackCheckmates) => println!("Checkmate! Winner: Black"),
-            Some(chess::GameResult::Stalemate) => println!("Stalemate!"),
-            Some(chess::GameResult::DrawAccepted) => println!("Draw!"),
-            _ => println!("GAME OVER"),
-        };
         Ok(())
     } else {
-        // setup for gui gamestate
-        let (playable1, bot_ref1) = if player1.player_type == PlayerType::Human {
-            (true, Bot::new(Color::White, 0, false))
-        } else {
-            (false, player1.bot_ref)
-        };
-        let (playable2, bot_ref2) = if player2.player_type == PlayerType::Human {
-            (true, Bot::new(Color::White, 0, false))
+        let mut gui_gamestate = GameState::new(game, halfmove_clock, players);
+        gui_gamestate.set_pgn_out(pgn_out);
+        gui_gamestate.set_undo_policy(undo);
+        if let Some(clock) = clock {
+            gui_gamestate.set_clock(clock);
+        }
+        println!("Starting gui...");
+        // run gui gameloop
+        chess_gui::run(gui_gamestate)
+    }
+}
+
+// step through the game in the commandline
+fn replay_in_commandline(pgn: &PgnGame) {
+    let moves = pgn.moves();
+    let mut boards = vec![pgn.start];
+    for m in &moves {
+        let board = boards[boards.len() - 1].make_move_new(*m);
+        boards.push(board);
+    }
+    let mut ply = 0;
+    loop {
+        print_board(&boards[ply], None);
+        if ply == 0 {
+            println!("Ply 0/{}: starting position", moves.len());
         } else {
-            (false, player2.bot_ref)
+            let m = numbered_move(&pgn.start, ply - 1, &boards[ply - 1], moves[ply - 1]);
+            println!("Ply {}/{}: {}", ply, moves.len(), m);
+        }
+        println!("Enter: next move, b: back, s: start, e: end, <number>: go to ply, q: quit");
+        ply = match stdin_get_input().as_str() {
+            "q" => break,
+            "" if ply == moves.len() => break,
+            "" | "n" => ply + 1,
+            "b" => ply.saturating_sub(1),
+            "s" => 0,
+            "e" => moves.len(),
+            input => match input.parse::<usize>() {
+                Ok(p) if p <= moves.len() => p,
+                _ => {
+                    println!("Invalid input");
+                    ply
+                }
+            },
         };
+    }
+}
 
-        let gui_gamestate = GameState::new(game, [playable1, playable2], [bot_ref1, bot_ref2]);
-        println!("Starting gui...");
-        // run gui gameloop
+// replay a game from a PGN file
+pub fn start_replay(setup: ReplaySetup) -> GameResult {
+    let error = |e: String| ggez::GameError::CustomError(e);
+    let text = fs::read_to_string(&setup.pgn)
+        .map_err(|e| error(format!("Could not read {}: {}", setup.pgn.display(), e)))?;
+    let games = parse_pgn(&text).map_err(error)?;
+    let pgn = setup
+        .game
+        .checked_sub(1)
+        .and_then(|i| games.get(i))
+        .ok_or_else(|| {
+            error(format!(
+                "There is no game {} in {}, it has {} games",
+                setup.game,
+                setup.pgn.display(),
+                games.len()
+            ))
+        })?;
+    println!(
+        "{} - {}, {}",
+        pgn.tag("White").unwrap_or("?"),
+        pgn.tag("Black").unwrap_or("?"),
+        pgn.tag("Result").unwrap_or("*")
+    );
+
+    if setup.visual == GameVisual::CommandLine {
+        replay_in_commandline(pgn);
+        Ok(())
+    } else {
+        println!("Step through the game with the arrow keys, home and end jump to the start and the end.");
+        let gui_gamestate = GameState::replay(pgn.start, pgn.moves());
         chess_gui::run(gui_gamestate)
     }
 }
+
+// play a match and print the score after every game
+pub fn start_match(setup: MatchSetup) -> GameResult {
+    let error = |e: String| ggez::GameError::CustomError(e);
+    let MatchSetup {
+        players,
+        names,
+        games,
+        openings,
+        clock,
+        concurrency,
+        pgn_out,
+        sprt,
+        results_out,
+    } = setup;
+    let openings = match openings {
+        Some(path) => {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| error(format!("Could not read {}: {}", path.display(), e)))?;
+            let openings = parse_openings(&text).map_err(error)?;
+            if openings.is_empty() {
+                return Err(error(format!(
+                    "There are no openings in {}",
+                    path.display()
+                )));
+            }
+            openings
+        }
+        None => Vec::new(),
+    };
+    let mut pgn_file = match &pgn_out {
+        Some(path) => Some(
+            fs::File::create(path)
+                .map_err(|e| error(format!("Could not create {}: {}", path.display(), e)))?,
+        ),
+        None => None,
+    };
+    let settings = MatchSettings {
+        games: games.unwrap_or(usize::MAX),
+        openings,
+        clock,
+        concurrency,
+    };
+    match games {
+        Some(games) => println!(
+            "{} vs {}: {} games, {} at a time",
+            names[0], names[1], games, concurrency
+        ),
+        None => println!(
+            "{} vs {}: until the SPRT decides, {} games at a time",
+            names[0], names[1], concurrency
+        ),
+    }
+    if let Some(sprt) = &sprt {
+        let (lower, upper) = sprt.bounds();
+        println!(
+            "SPRT: elo0 {}, elo1 {}, alpha {}, beta {}, {:?}, bounds [{:.2}, {:.2}]",
+            sprt.elo0, sprt.elo1, sprt.alpha, sprt.beta, sprt.model, lower, upper
+        );
+    }
+    let create = |i: usize, color: Color| {
+        players[i]
+            .create(color, &GameVisual::CommandLine)
+            .map_err(|e| format!("Could not start {}: {}", names[i], e))
+    };
+    let mut pairs = PairScore::default();
+    let score = run_match(&settings, create, |game, score| {
+        let (white, black) = match game.first {
+            Color::White => (&names[0], &names[1]),
+            Color::Black => (&names[1], &names[0]),
+        };
+        println!(
+            "Game {} ({} - {}): {} {}",
+            game.number,
+            white,
+            black,
+            game.outcome.result_tag(),
+            game.outcome
+        );
+        if let Some(e) = &game.error {
+            println!("  {}", e);
+        }
+        println!("  {}", score);
+        pairs.add(game.pair(), half_points(game.outcome, game.first));
+        if let (Some(file), Some(path)) = (&mut pgn_file, &pgn_out) {
+            let header = PgnHeader {
+                event: format!("{} vs {}", names[0], names[1]),
+                round: game.number.to_string(),
+                ..PgnHeader::new(white, black)
+            };
+            let record = &game.record;
+            let pgn = to_pgn(
+                &header,
+                record.start(),
+                record.game(),
+                Some(game.outcome),
+                record.searches(),
+            );
+            if let Err(e) = writeln!(file, "{}", pgn) {
+                println!("Could not save the game to {}: {}", path.display(), e);
+            }
+        }
+        match &sprt {
+            Some(sprt) => {
+                let llr = sprt.llr(score, &pairs);
+                let (lower, upper) = sprt.bounds();
+                println!("  LLR {:.2} [{:.2}, {:.2}]", llr, lower, upper);
+                sprt.result(llr).is_none()
+            }
+            None => true,
+        }
+    })
+    .map_err(error)?;
+    println!();
+    println!("{} vs {}, {} games", names[0], names[1], score.games());
+    println!("{}", score);
+    println!("Pairs with 0, 1/2, 1, 3/2 and 2 points: {:?}", pairs.counts);
+    if let Some(sprt) = &sprt {
+        let llr = sprt.llr(&score, &pairs);
+        match sprt.result(llr) {
+            Some(SprtResult::H1) => println!(
+                "SPRT: H1 accepted (LLR {:.2}), {} is at least {} Elo stronger",
+                llr, names[0], sprt.elo1
+            ),
+            Some(SprtResult::H0) => println!(
+                "SPRT: H0 accepted (LLR {:.2}), {} is at most {} Elo stronger",
+                llr, names[0], sprt.elo0
+            ),
+            None => println!("SPRT: no decision (LLR {:.2})", llr),
+        }
+    }
+    if let Some(path) = &results_out {
+        let report = MatchReport {
+            players: [&names[0], &names[1]],
+            score,
+            pairs: &pairs,
+            sprt,
+        };
+        fs::write(path, report.to_json() + "\n")
+            .map_err(|e| error(format!("Could not write {}: {}", path.display(), e)))?;
+    }
+    Ok(())
+}
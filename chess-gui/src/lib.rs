@@ -1,10 +1,40 @@
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn replays_a_mated_game() {
+        let moves = ["f2f3", "e7e5", "g2g4", "d8h4"]
+            .iter()
+            .map(|m| ChessMove::from_str(m).unwrap())
+            .collect();
+        let mut state = GameState::replay(Board::default(), moves);
+        assert!(state.board_keys(|key| key == event::KeyCode::End));
+        assert_eq!(
+            state.outcome(),
+            Some(Outcome::Checkmate(chess::Color::White))
+        );
+        // the keys still work in the mated position
+        for (key, ply) in [
+            (event::KeyCode::Left, 3),
+            (event::KeyCode::End, 4),
+            (event::KeyCode::Home, 0),
+        ] {
+            state.step_timeout = 0;
+            assert!(state.board_keys(|k| k == key));
+            assert_eq!(state.record.ply(), ply);
+        }
+        assert_eq!(state.outcome(), None);
+        let pov = state.pov;
+        assert!(state.board_keys(|key| key == event::KeyCode::F));
+        assert_ne!(state.pov, pov);
+    }
 }
 
 // imports
@@ -12,7 +42,12 @@ use chess::{self, BitBoard, Board, ChessMove, File, Game, MoveGen, Piece, Rank,
 use std::str::FromStr;
 use std::usize;
 
-use chess_ai::Bot;
+use chess_ai::clock::{format_time, Clock, ClockSettings};
+use chess_ai::outcome::Outcome;
+use chess_ai::pgn::{numbered_move, to_pgn, PgnHeader};
+use chess_ai::player::{Player, PlayerAction, ScriptedPlayer};
+use chess_ai::record::{GameRecord, UndoPolicy};
+use chess_ai::san::line_to_san;
 
 use ggez::conf::{WindowMode, WindowSetup};
 use ggez::event;
@@ -22,11 +57,14 @@ use ggez::input::{keyboard, mouse};
 use ggez::timer;
 use ggez::{Context, GameResult};
 use std::env;
+use std::fs;
 use std::io::{stdin, stdout, Write};
 use std::path;
 
 // constants
 const WINDWOW_SIZE: f32 = 800.;
+// height of the bar below the board showing the clocks
+const CLOCK_BAR: f32 = 40.;
 const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 // helper functions for canvas & board conversion
@@ -69,61 +107,393 @@ fn movegen(board: &Board, start_square: Square, color_to_move: chess::Color) ->
     }
 }
 
+// human player entering moves on the board
+#[derive(Default)]
+pub struct GuiHuman {
+    // move entered on the board, not yet played
+    entered: Option<ChessMove>,
+}
+
+impl GuiHuman {
+    pub fn new() -> GuiHuman {
+        GuiHuman::default()
+    }
+}
+
+impl Player for GuiHuman {
+    fn name(&self) -> String {
+        "Human".to_string()
+    }
+
+    fn choose_move(&mut self, _game: &Game) -> PlayerAction {
+        match self.entered.take() {
+            Some(m) => PlayerAction::Move(m),
+            None => PlayerAction::Wait,
+        }
+    }
+
+    fn accepts_input(&self) -> bool {
+        true
+    }
+
+    fn input(&mut self, m: ChessMove) {
+        self.entered = Some(m);
+    }
+}
+
+// moves of a game being replayed
+struct Replay {
+    moves: Vec<ChessMove>,
+    // number of moves made on the board
+    ply: usize,
+}
+
 // holds the state of the current game
 pub struct GameState {
     pov: u8,
     flip_timeout: u16,
+    // like flip_timeout, for stepping through replays and takebacks
+    step_timeout: u16,
     field_selected: bool,
     field: (i16, i16),
-    game: Game,
+    // the game with the searches behind its moves, for saving the game and taking back moves
+    record: GameRecord,
     current_legal_moves: Vec<ChessMove>,
-    playable: [bool; 2],
-    bot_refs: [Bot; 2],
+    // white and black, asked for their move in turn
+    players: [Box<dyn Player>; 2],
+    undo: UndoPolicy,
+    header: PgnHeader,
+    // the game is saved there after every move
+    pgn_out: Option<path::PathBuf>,
+    // set when replaying a game instead of playing one
+    replay: Option<Replay>,
+    clock: Option<Clock>,
+    // set when a flag falls, chess::Game doesn't know about time
+    time_forfeit: Option<Outcome>,
 }
 
 impl GameState {
-    pub fn new(game: Game, playable: [bool; 2], bot_refs: [Bot; 2]) -> GameState {
-        let pov = if !playable[0] && playable[1] { 2 } else { 1 };
+    // `halfmove_clock` is the one of the starting position, from its FEN
+    pub fn new(game: Game, halfmove_clock: u32, mut players: [Box<dyn Player>; 2]) -> GameState {
+        // look at the board from black's side if only black is entering moves
+        let pov = if !players[0].accepts_input() && players[1].accepts_input() {
+            2
+        } else {
+            1
+        };
+        let start = game.current_position();
+        for player in players.iter_mut() {
+            player.new_game(&start, halfmove_clock);
+        }
+        let header = PgnHeader::new(&players[0].name(), &players[1].name());
         let s = GameState {
             pov,
             flip_timeout: 0,
+            step_timeout: 0,
             field_selected: false,
             field: (-1, -1),
-            game,
+            record: GameRecord::new(game, halfmove_clock),
             current_legal_moves: movegen_empty(),
-            playable,
-            bot_refs,
+            players,
+            undo: UndoPolicy::default(),
+            header,
+            pgn_out: None,
+            replay: None,
+            clock: None,
+            time_forfeit: None,
         };
 
         s
     }
+
+    // step through the moves of a finished game with the arrow keys
+    pub fn replay(start: Board, moves: Vec<ChessMove>) -> GameState {
+        // replays have no players asking for moves
+        let players: [Box<dyn Player>; 2] = [
+            Box::new(ScriptedPlayer::new(Vec::new())),
+            Box::new(ScriptedPlayer::new(Vec::new())),
+        ];
+        let mut s = GameState::new(Game::new_with_board(start), 0, players);
+        s.replay = Some(Replay { moves, ply: 0 });
+        s
+    }
+
+    // keys that work whether the game goes on, is over or is replayed: F flips the board, the arrow keys,
+    // home and end step through replays. `pressed` tells whether a key is held down.
+    // Returns whether a game is replayed
+    fn board_keys(&mut self, pressed: impl Fn(event::KeyCode) -> bool) -> bool {
+        // flip board orientation, set timeout to make sure it doesn't switch back and forth too quickly
+        if self.flip_timeout == 0 && pressed(event::KeyCode::F) {
+            self.pov = (self.pov % 2) + 1;
+            self.flip_timeout = 10;
+            self.current_legal_moves = movegen_empty();
+            self.field_selected = false;
+        }
+        if self.replay.is_none() {
+            return false;
+        }
+        self.step_replay(pressed);
+        true
+    }
+
+    fn step_replay(&mut self, pressed: impl Fn(event::KeyCode) -> bool) {
+        let replay = self.replay.as_mut().expect("Replaying a game");
+        if self.step_timeout > 0 {
+            return;
+        }
+        let ply = if pressed(event::KeyCode::Right) {
+            (replay.ply + 1).min(replay.moves.len())
+        } else if pressed(event::KeyCode::Left) {
+            replay.ply.saturating_sub(1)
+        } else if pressed(event::KeyCode::Home) {
+            0
+        } else if pressed(event::KeyCode::End) {
+            replay.moves.len()
+        } else {
+            return;
+        };
+        self.step_timeout = 10;
+        if ply == replay.ply {
+            return;
+        }
+        replay.ply = ply;
+        let start = *self.record.start();
+        let halfmove_clock = self.record.start_halfmove_clock();
+        self.record = GameRecord::new(Game::new_with_board(start), halfmove_clock);
+        for m in &replay.moves[..ply] {
+            self.record.make_move(*m, None);
+        }
+        self.field_selected = false;
+        self.current_legal_moves = movegen_empty();
+        if ply == 0 {
+            println!("Starting position");
+        } else {
+            let mut board = start;
+            for m in &replay.moves[..ply - 1] {
+                board = board.make_move_new(*m);
+            }
+            let m = replay.moves[ply - 1];
+            println!("{}", numbered_move(&start, ply - 1, &board, m));
+        }
+    }
+
+    pub fn set_pgn_out(&mut self, path: Option<path::PathBuf>) {
+        self.pgn_out = path;
+    }
+
+    pub fn set_undo_policy(&mut self, policy: UndoPolicy) {
+        self.undo = policy;
+    }
+
+    // press U to take back moves and R to play them again, while a human is to move or after the game
+    fn step_takeback(&mut self, ctx: &Context) {
+        if self.replay.is_some() || self.step_timeout > 0 {
+            return;
+        }
+        let undo = keyboard::is_key_pressed(ctx, event::KeyCode::U);
+        if !undo && !keyboard::is_key_pressed(ctx, event::KeyCode::R) {
+            return;
+        }
+        self.step_timeout = 10;
+        if self.clock.is_some() {
+            println!("Moves can't be taken back in games with a clock");
+            return;
+        }
+        let color = self.record.game().side_to_move();
+        if self.outcome().is_none() && !self.players[color.to_index()].accepts_input() {
+            println!("Moves can only be taken back while a human is to move");
+            return;
+        }
+        let players = &self.players;
+        let human = |color: chess::Color| players[color.to_index()].accepts_input();
+        let plies = if undo {
+            self.record.undo_with(self.undo, human)
+        } else {
+            self.record.redo_with(self.undo, human)
+        };
+        match (undo, plies) {
+            (true, 0) => println!("Nothing to take back"),
+            (true, _) => println!("Took back {} plies", plies),
+            (false, 0) => println!("Nothing to redo"),
+            (false, _) => println!("Played {} plies again", plies),
+        }
+        self.field_selected = false;
+        self.current_legal_moves = movegen_empty();
+        self.save_pgn();
+    }
+
+    // keys for the human to move: Q resigns, D offers a draw, A accepts and N declines the opponent's offer,
+    // C claims a draw by threefold repetition or the 50-move rule
+    fn key_action(&mut self, ctx: &Context) -> Option<PlayerAction> {
+        if self.step_timeout > 0 {
+            return None;
+        }
+        let keys = [
+            (event::KeyCode::Q, PlayerAction::Resign),
+            (event::KeyCode::D, PlayerAction::OfferDraw),
+            (event::KeyCode::A, PlayerAction::AcceptDraw),
+            (event::KeyCode::N, PlayerAction::DeclineDraw),
+            (event::KeyCode::C, PlayerAction::ClaimDraw),
+        ];
+        let (_, action) = keys
+            .into_iter()
+            .find(|(key, _)| keyboard::is_key_pressed(ctx, *key))?;
+        self.step_timeout = 10;
+        Some(action)
+    }
+
+    // play with a chess clock, it starts with the first update
+    pub fn set_clock(&mut self, settings: ClockSettings) {
+        self.clock = Some(Clock::new(settings));
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        self.time_forfeit.or_else(|| self.record.outcome())
+    }
+
+    fn save_pgn(&self) {
+        if let Some(path) = &self.pgn_out {
+            let pgn = to_pgn(
+                &self.header,
+                self.record.start(),
+                self.record.game(),
+                self.outcome(),
+                self.record.searches(),
+            );
+            if let Err(e) = fs::write(path, pgn) {
+                println!("Could not save the game to {}: {}", path.display(), e);
+            }
+        }
+    }
 }
 
 impl event::EventHandler<ggez::GameError> for GameState {
     // gets called on update events
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        if !self.game.result().is_none() {
+        self.step_takeback(ctx);
+        // replays end in the final position, which may have an outcome
+        if self.board_keys(|key| keyboard::is_key_pressed(ctx, key)) || self.outcome().is_some() {
             return Ok(());
         }
-        // variable to index into playable and bot_ref arrays
-        let current_player_as_idx = if self.game.side_to_move() == chess::Color::White {
+        // variable to index into the players array
+        let current_player_as_idx = if self.record.game().side_to_move() == chess::Color::White {
             0
         } else {
             1
         } as usize;
 
-        // flip board orientation, set timeout to make sure it doesn't switch back and forth too quickly
-        if self.flip_timeout == 0 && keyboard::is_key_pressed(ctx, event::KeyCode::F) {
-            self.pov = (self.pov % 2) + 1;
-            self.flip_timeout = 10;
-            self.current_legal_moves = movegen_empty();
-            self.field_selected = false;
+        // press S to make a searching player move now, with the best move found so far
+        if keyboard::is_key_pressed(ctx, event::KeyCode::S) {
+            self.players[current_player_as_idx].stop();
         }
 
-        // player clicks on a square
-        if mouse::button_pressed(ctx, mouse::MouseButton::Left) {
-            // if current player is not a bot
-            if self.playable[current_player_as_idx] {
+        let color = self.record.game().side_to_move();
+        let key_action = if self.players[current_player_as_idx].accepts_input() {
+            self.key_action(ctx)
+        } else {
+            None
+        };
+        let opponent_is_human = self.players[1 - current_player_as_idx].accepts_input();
+        let player = &mut self.players[current_player_as_idx];
+        // start the clock of the side to move, its player gets the time left for its search
+        if let Some(clock) = &mut self.clock {
+            if clock.running().is_none() {
+                clock.start(color);
+                player.set_clock(clock.limits(color));
+            }
+        }
+        let action = key_action.unwrap_or_else(|| player.choose_move(self.record.game()));
+        match action {
+            PlayerAction::Move(m) => {
+                let in_time = match &mut self.clock {
+                    Some(clock) => clock.press(),
+                    None => true,
+                };
+                let board = self.record.game().current_position();
+                let search = player.search_result().cloned();
+                if let Some(result) = &search {
+                    println!(
+                        "depth {} score {} time {:.2?}, expected line: {}",
+                        result.depth,
+                        result.score,
+                        result.time,
+                        line_to_san(&board, &result.pv)
+                    );
+                }
+                if !in_time {
+                    self.time_forfeit = Some(Outcome::time_forfeit(&board, color));
+                } else if board.legal(m) {
+                    let ply = self.record.ply();
+                    println!("{}", numbered_move(self.record.start(), ply, &board, m));
+                    self.record.make_move(m, search);
+                } else {
+                    println!("{} played an illegal move: {}", player.name(), m);
+                    self.record.resign(color);
+                }
+                self.save_pgn();
+            }
+            PlayerAction::Resign => {
+                if let Some(e) = player.error() {
+                    println!("{}", e);
+                }
+                self.record.resign(color);
+                self.save_pgn();
+            }
+            PlayerAction::OfferDraw => {
+                if self.record.offer_draw(color) {
+                    println!("{} offers a draw", player.name());
+                    if opponent_is_human {
+                        println!("After the move, press A to accept or N to decline");
+                    }
+                }
+            }
+            PlayerAction::AcceptDraw => {
+                if self.record.accept_draw() {
+                    self.save_pgn();
+                } else {
+                    println!("There is no draw offer to accept");
+                }
+            }
+            PlayerAction::DeclineDraw => {
+                if self.record.decline_draw() {
+                    println!("{} declines the draw", player.name());
+                } else {
+                    println!("There is no draw offer to decline");
+                }
+            }
+            PlayerAction::ClaimDraw => {
+                if self.record.claim_draw() {
+                    self.save_pgn();
+                } else {
+                    println!("No draw can be claimed, the position hasn't occurred three times and there were captures or pawn moves in the last 50 moves");
+                }
+            }
+            // takebacks come from the keyboard in the gui
+            PlayerAction::Undo | PlayerAction::Redo => {}
+            PlayerAction::Wait => {
+                if self
+                    .clock
+                    .as_ref()
+                    .is_some_and(|clock| clock.flagged(color))
+                {
+                    player.stop();
+                    self.time_forfeit = Some(Outcome::time_forfeit(
+                        &self.record.game().current_position(),
+                        color,
+                    ));
+                    self.save_pgn();
+                }
+            }
+        }
+
+        // player clicks on a square, while still waiting for the move
+        if action == PlayerAction::Wait
+            && self.outcome().is_none()
+            && mouse::button_pressed(ctx, mouse::MouseButton::Left)
+            && mouse::position(ctx).y < WINDWOW_SIZE
+        {
+            // if current player enters moves on the board
+            if self.players[current_player_as_idx].accepts_input() {
                 let canvas_square_clicked = canvas_coord_to_canvas_square(
                     mouse::position(ctx).x as i16,
                     mouse::position(ctx).y as i16,
@@ -135,9 +505,9 @@ impl event::EventHandler<ggez::GameError> for GameState {
                     if !self.field_selected {
                         let square = canvas_square_to_board_square(canvas_square_clicked, self.pov);
                         self.current_legal_moves = movegen(
-                            &self.game.current_position(),
+                            &self.record.game().current_position(),
                             square,
-                            self.game.side_to_move(),
+                            self.record.game().side_to_move(),
                         );
                         self.field = canvas_square_clicked;
                         self.field_selected = true;
@@ -149,23 +519,28 @@ impl event::EventHandler<ggez::GameError> for GameState {
 
                         // get pieces on start & target square
                         let (is_piece_1, piece1) =
-                            match self.game.current_position().piece_on(start_square) {
-                                Some(x) => (true, x),
-                                None => (false, Piece::Pawn),
-                            };
-                        let (is_piece_2, _) =
-                            match self.game.current_position().piece_on(target_square) {
+                            match self.record.game().current_position().piece_on(start_square) {
                                 Some(x) => (true, x),
                                 None => (false, Piece::Pawn),
                             };
+                        let (is_piece_2, _) = match self
+                            .record
+                            .game()
+                            .current_position()
+                            .piece_on(target_square)
+                        {
+                            Some(x) => (true, x),
+                            None => (false, Piece::Pawn),
+                        };
                         // get current board
-                        let board = self.game.current_position();
+                        let board = self.record.game().current_position();
 
                         // check if the move can be made
                         if is_piece_1
                             && (board.color_on(start_square) != board.color_on(target_square)
                                 || !is_piece_2)
-                            && Some(self.game.side_to_move()) == board.color_on(start_square)
+                            && Some(self.record.game().side_to_move())
+                                == board.color_on(start_square)
                             && self
                                 .current_legal_moves
                                 .iter()
@@ -184,9 +559,9 @@ impl event::EventHandler<ggez::GameError> for GameState {
                                 None
                             };
 
-                            // make the move
-                            self.game
-                                .make_move(ChessMove::new(start_square, target_square, prom));
+                            // hand the move to the player, it's made on the next update
+                            let m = ChessMove::new(start_square, target_square, prom);
+                            self.players[current_player_as_idx].input(m);
                             // reset field and legal moves
                             self.field_selected = false;
                             self.current_legal_moves = movegen_empty();
@@ -194,15 +569,10 @@ impl event::EventHandler<ggez::GameError> for GameState {
                             // if no move is possible -> select new field
                             self.field = canvas_square_clicked;
                             self.current_legal_moves =
-                                movegen(&board, target_square, self.game.side_to_move());
+                                movegen(&board, target_square, self.record.game().side_to_move());
                         }
                     }
                 }
-            } else {
-                // if the current player is a bot, let the bot make a move
-                self.game.make_move(
-                    self.bot_refs[current_player_as_idx].get_move(self.game.current_position()),
-                );
             }
             // press the right mouse button to deselect fields
             if mouse::button_pressed(ctx, mouse::MouseButton::Right) {
@@ -212,14 +582,8 @@ impl event::EventHandler<ggez::GameError> for GameState {
             }
         }
         // if game is over, print result
-        if !self.game.result().is_none() {
-            match self.game.result() {
-                Some(chess::GameResult::WhiteCheckmates) => println!("Checkmate! Winner: White"),
-                Some(chess::GameResult::BlackCheckmates) => println!("Checkmate! Winner: Black"),
-                Some(chess::GameResult::Stalemate) => println!("Stalemate!"),
-                Some(chess::GameResult::DrawAccepted) => println!("Draw!"),
-                _ => println!("GAME OVER"),
-            };
+        if let Some(outcome) = self.outcome() {
+            println!("{}", outcome);
         }
         Ok(())
     }
@@ -229,11 +593,18 @@ impl event::EventHandler<ggez::GameError> for GameState {
         if self.flip_timeout > 0 {
             self.flip_timeout -= 1;
         }
+        if self.step_timeout > 0 {
+            self.step_timeout -= 1;
+        }
         let tile_size = (WINDWOW_SIZE as u32 / 8) as f32;
         graphics::clear(ctx, [1., 1., 1., 1.0].into());
-        let color_to_move = self.game.side_to_move();
+        let color_to_move = self.record.game().side_to_move();
         let king_square = board_square_to_canvas_square(
-            &self.game.current_position().king_square(color_to_move),
+            &self
+                .record
+                .game()
+                .current_position()
+                .king_square(color_to_move),
             self.pov,
         );
 
@@ -256,7 +627,7 @@ impl event::EventHandler<ggez::GameError> for GameState {
                     > 0
                 {
                     Color::from((200, 80, 80, 255))
-                } else if self.game.current_position().checkers().popcnt() > 0
+                } else if self.record.game().current_position().checkers().popcnt() > 0
                     && (x as f32, y as f32) == king_square
                 {
                     // king square && king is in check
@@ -305,7 +676,7 @@ impl event::EventHandler<ggez::GameError> for GameState {
         ];
         let img_size = piece_imgs[0][0].width() as f32;
         let offset = (tile_size - img_size) / 2.0;
-        let board = self.game.current_position();
+        let board = self.record.game().current_position();
 
         // loop over all squares and draw the pieces
         for i in 0..8 {
@@ -340,6 +711,33 @@ impl event::EventHandler<ggez::GameError> for GameState {
             }
         }
 
+        // clocks below the board, the running one in red
+        if let Some(clock) = &self.clock {
+            for color in [chess::Color::White, chess::Color::Black] {
+                let name = if color == chess::Color::White {
+                    "White"
+                } else {
+                    "Black"
+                };
+                let text_color = if clock.running() == Some(color) {
+                    Color::from((200, 30, 30, 255))
+                } else {
+                    Color::BLACK
+                };
+                let text = graphics::Text::new(
+                    graphics::TextFragment::new(format!(
+                        "{} {}",
+                        name,
+                        format_time(clock.remaining(color))
+                    ))
+                    .color(text_color)
+                    .scale(graphics::PxScale::from(28.)),
+                );
+                let x = color.to_index() as f32 * WINDWOW_SIZE / 2. + 20.;
+                graphics::draw(ctx, &text, (glam::Vec2::new(x, WINDWOW_SIZE + 6.),))?;
+            }
+        }
+
         graphics::present(ctx)?;
 
         Ok(())
@@ -365,7 +763,11 @@ pub fn run(gamestate: GameState) -> GameResult {
         })
         .window_mode(WindowMode {
             width: WINDWOW_SIZE,
-            height: WINDWOW_SIZE,
+            height: if gamestate.clock.is_some() {
+                WINDWOW_SIZE + CLOCK_BAR
+            } else {
+                WINDWOW_SIZE
+            },
             resizable: false,
             ..WindowMode::default()
         });
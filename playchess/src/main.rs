@@ -1,7 +1,27 @@
 use chess_gamesetup as setup;
 use ggez::GameResult;
+use std::env;
+use std::process;
 
 fn main() -> GameResult {
-    let (p1, p2, game, visual) = setup::command_line_setup();
-    setup::start_game(p1, p2, game, visual)
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", setup::USAGE);
+        return Ok(());
+    }
+    // ask for the setup if there are no arguments
+    let command = if args.is_empty() {
+        setup::command_line_setup().map(setup::Command::Play)
+    } else {
+        setup::parse_args(&args)
+    };
+    match command {
+        Ok(setup::Command::Play(game_setup)) => setup::start_game(game_setup),
+        Ok(setup::Command::Replay(replay)) => setup::start_replay(replay),
+        Ok(setup::Command::Match(setup)) => setup::start_match(setup),
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, setup::USAGE);
+            process::exit(2);
+        }
+    }
 }